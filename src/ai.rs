@@ -0,0 +1,282 @@
+//! Computer player for Reversi. `Ai` is a sync actor, so the alpha-beta
+//! search runs on its own threads and never blocks the `Server` actor.
+
+use actix::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::reversi::{Board, Geometry};
+
+/// Value of each square for the static evaluation. On 8x8 this is
+///
+/// ```text
+/// 100 -20  10   5   5  10 -20 100
+/// -20 -50  -2  -2  -2  -2 -50 -20
+///  10  -2  -1  -1  -1  -1  -2  10
+///   5  -2  -1  -1  -1  -1  -2   5
+/// ...
+/// ```
+fn weights(size: usize) -> Vec<i32> {
+    let last = size - 1;
+    let edge = |v: usize| v == 0 || v == last;
+    let next_to_edge = |v: usize| v == 1 || v == last - 1;
+    let mut ret = Vec::with_capacity(size * size);
+    for y in 0..size {
+        for x in 0..size {
+            let w = if edge(x) && edge(y) {
+                100
+            } else if next_to_edge(x) && next_to_edge(y) {
+                -50
+            } else if (edge(x) && next_to_edge(y)) || (next_to_edge(x) && edge(y)) {
+                -20
+            } else if edge(x) || edge(y) {
+                let other = if edge(x) { y } else { x };
+                if other == 2 || other == last - 2 {
+                    10
+                } else {
+                    5
+                }
+            } else if next_to_edge(x) || next_to_edge(y) {
+                -2
+            } else {
+                -1
+            };
+            ret.push(w);
+        }
+    }
+    ret
+}
+
+/// Score of a finished game is the disc difference times this
+const WIN: i32 = 10_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Level {
+    pub fn parse(s: &str) -> Option<Level> {
+        match s {
+            "easy" => Some(Level::Easy),
+            "normal" => Some(Level::Normal),
+            "hard" => Some(Level::Hard),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Easy => "easy",
+            Level::Normal => "normal",
+            Level::Hard => "hard",
+        }
+    }
+
+    /// Maximum search depth in plies
+    fn depth(self) -> u32 {
+        match self {
+            Level::Easy => 1,
+            Level::Normal => 4,
+            Level::Hard => 10,
+        }
+    }
+
+    /// Time after which the search stops deepening
+    fn time(self) -> Duration {
+        match self {
+            Level::Easy => Duration::from_millis(100),
+            Level::Normal => Duration::from_millis(500),
+            Level::Hard => Duration::from_secs(3),
+        }
+    }
+}
+
+struct Search {
+    geometry: Geometry,
+    weights: Vec<i32>,
+    deadline: Instant,
+    nodes: u64,
+    aborted: bool,
+}
+
+impl Search {
+    fn evaluate(&self, own: u128, opp: u128) -> i32 {
+        let g = &self.geometry;
+        let mut score = 0;
+        for (square, weight) in self.weights.iter().enumerate() {
+            if own & 1 << square != 0 {
+                score += weight;
+            } else if opp & 1 << square != 0 {
+                score -= weight;
+            }
+        }
+        let mobility =
+            g.moves(own, opp).count_ones() as i32 - g.moves(opp, own).count_ones() as i32;
+        score + mobility * 5
+    }
+
+    fn negamax(&mut self, own: u128, opp: u128, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(1024) && Instant::now() >= self.deadline {
+            self.aborted = true;
+        }
+        if self.aborted {
+            return 0;
+        }
+        let g = self.geometry;
+        let mut legal = g.moves(own, opp);
+        if legal == 0 {
+            if g.moves(opp, own) == 0 {
+                return (own.count_ones() as i32 - opp.count_ones() as i32) * WIN;
+            }
+            // pass
+            return -self.negamax(opp, own, depth, -beta, -alpha);
+        }
+        if depth == 0 {
+            return self.evaluate(own, opp);
+        }
+        while legal != 0 {
+            let square = legal.trailing_zeros() as usize;
+            legal &= legal - 1;
+            let f = g.flips(own, opp, square);
+            let score = -self.negamax(opp & !f, own | f | 1 << square, depth - 1, -beta, -alpha);
+            if score > alpha {
+                alpha = score;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        alpha
+    }
+
+    /// Best move at the root for one depth, `first` is searched first
+    fn root(&mut self, own: u128, opp: u128, depth: u32, first: usize) -> usize {
+        let g = self.geometry;
+        let legal = g.moves(own, opp);
+        let mut order = vec![first];
+        let mut rest = legal & !(1 << first);
+        while rest != 0 {
+            order.push(rest.trailing_zeros() as usize);
+            rest &= rest - 1;
+        }
+        let (mut best, mut alpha) = (first, -i32::MAX);
+        for square in order {
+            let f = g.flips(own, opp, square);
+            let score = -self.negamax(
+                opp & !f,
+                own | f | 1 << square,
+                depth - 1,
+                -i32::MAX,
+                -alpha,
+            );
+            if score > alpha {
+                alpha = score;
+                best = square;
+            }
+        }
+        best
+    }
+}
+
+/// Searches the best square for the player to move, `None` if it has to pass
+pub fn best_move(board: Board, player1: bool, level: Level) -> Option<(usize, usize)> {
+    let (own, opp) = if player1 {
+        (board.white, board.black)
+    } else {
+        (board.black, board.white)
+    };
+    let g = board.geometry;
+    let legal = g.moves(own, opp);
+    if legal == 0 {
+        return None;
+    }
+    let mut search = Search {
+        geometry: g,
+        weights: weights(g.size),
+        deadline: Instant::now() + level.time(),
+        nodes: 0,
+        aborted: false,
+    };
+    let mut best = legal.trailing_zeros() as usize;
+    for depth in 1..=level.depth() {
+        let square = search.root(own, opp, depth, best);
+        if search.aborted {
+            break;
+        }
+        best = square;
+    }
+    Some((best % g.size, best / g.size))
+}
+
+/// Ask the AI for a move
+#[derive(Message)]
+#[rtype(result = "Option<(usize, usize)>")]
+pub struct Think {
+    pub board: Board,
+    /// true if the AI plays player1's discs
+    pub player1: bool,
+    pub level: Level,
+}
+
+pub struct Ai;
+
+impl Actor for Ai {
+    type Context = SyncContext<Self>;
+}
+
+impl Handler<Think> for Ai {
+    type Result = Option<(usize, usize)>;
+
+    fn handle(&mut self, msg: Think, _: &mut Self::Context) -> Self::Result {
+        best_move(msg.board, msg.player1, msg.level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{best_move, weights, Level};
+    use crate::reversi::{Board, Reversi, States};
+
+    #[test]
+    fn takes_the_corner() {
+        let mut discs = vec![vec![0; 8]; 8];
+        discs[0][1] = 2;
+        discs[0][2] = 1;
+        discs[4][4] = 2;
+        discs[4][5] = 1;
+        let board = Board::from_discs(&discs);
+        assert_eq!(best_move(board, true, Level::Normal), Some((0, 0)));
+        assert_eq!(best_move(Board::default(), true, Level::Easy), None);
+    }
+
+    #[test]
+    fn square_weights() {
+        let w = weights(8);
+        assert_eq!(&w[..8], &[100, -20, 10, 5, 5, 10, -20, 100]);
+        assert_eq!(&w[8..16], &[-20, -50, -2, -2, -2, -2, -50, -20]);
+        assert_eq!(&w[24..32], &[5, -2, -1, -1, -1, -1, -2, 5]);
+        assert_eq!(weights(6).len(), 36);
+    }
+
+    #[test]
+    fn plays_a_whole_game() {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        r.init();
+        while *r.state.borrow() != States::End {
+            let (id, player1, level) = match *r.state.borrow() {
+                States::TurnPlayer1 => (1, true, Level::Easy),
+                _ => (2, false, Level::Normal),
+            };
+            let (x, y) = best_move(r.board.get(), player1, level).unwrap();
+            r.set_disc(id, x, y).unwrap();
+        }
+        assert!(r.result.borrow().is_some());
+    }
+}
@@ -0,0 +1,299 @@
+//! Game clocks for timed games. `Clock` only does the bookkeeping, the
+//! `Server` actor drives it with an interval and flags the player whose
+//! time has run out.
+
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+/// Longest main time, a day
+pub const MAX_BASE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Longest Fischer increment or byo-yomi period, an hour
+pub const MAX_PERIOD: Duration = Duration::from_secs(60 * 60);
+/// Most byo-yomi periods
+pub const MAX_PERIODS: u32 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeControl {
+    /// `base` for the whole game, `increment` is added after every move
+    Fischer { base: Duration, increment: Duration },
+    /// `base` for the whole game, then every move must be made within
+    /// `period`. A move that takes longer uses up one of the `periods`.
+    ByoYomi {
+        base: Duration,
+        period: Duration,
+        periods: u32,
+    },
+}
+
+impl TimeControl {
+    /// Parses a `/start` option, `time=300+5` for 300 seconds with a 5 second
+    /// increment or `byoyomi=600+30x3` for 600 seconds and three 30 second
+    /// periods
+    pub fn parse(key: &str, value: &str) -> Option<TimeControl> {
+        let (base, rest) = value.split_once('+')?;
+        let base = Duration::from_secs(base.parse().ok()?);
+        let control = match key {
+            "time" => TimeControl::Fischer {
+                base,
+                increment: Duration::from_secs(rest.parse().ok()?),
+            },
+            "byoyomi" => {
+                let (period, periods) = rest.split_once('x')?;
+                TimeControl::ByoYomi {
+                    base,
+                    period: Duration::from_secs(period.parse().ok()?),
+                    periods: periods.parse().ok()?,
+                }
+            }
+            _ => return None,
+        };
+        Some(control).filter(TimeControl::valid)
+    }
+
+    /// Within `MAX_BASE`, `MAX_PERIOD` and `MAX_PERIODS`, with at least
+    /// one byo-yomi period
+    pub fn valid(&self) -> bool {
+        match *self {
+            TimeControl::Fischer { base, increment } => base <= MAX_BASE && increment <= MAX_PERIOD,
+            TimeControl::ByoYomi {
+                base,
+                period,
+                periods,
+            } => {
+                base <= MAX_BASE
+                    && period > Duration::from_secs(0)
+                    && period <= MAX_PERIOD
+                    && (1..=MAX_PERIODS).contains(&periods)
+            }
+        }
+    }
+}
+
+/// Remaining time of one player
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PlayerTime {
+    /// main time in milliseconds
+    pub remaining: u64,
+    /// byo-yomi periods, 0 for Fischer clocks
+    pub periods: u32,
+}
+
+/// What clients need to display the clock
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ClockState {
+    pub player1: PlayerTime,
+    pub player2: PlayerTime,
+    /// 1 or 2, the player whose clock is running
+    pub running: Option<u8>,
+}
+
+#[derive(Debug)]
+pub struct Clock {
+    control: TimeControl,
+    /// main time left, indexed by player1 = 0 and player2 = 1
+    remaining: [Duration; 2],
+    periods: [u32; 2],
+    /// player whose clock runs and since when
+    running: Option<(usize, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        let (base, periods) = match control {
+            TimeControl::Fischer { base, .. } => (base, 0),
+            TimeControl::ByoYomi { base, periods, .. } => (base, periods),
+        };
+        Clock {
+            control,
+            remaining: [base; 2],
+            periods: [periods; 2],
+            running: None,
+        }
+    }
+
+    /// Charge the running player for the time it used and run the clock
+    /// of `player` (0 or 1), which may be the same player after a pass.
+    /// `None` stops the clock.
+    pub fn switch(&mut self, player: Option<usize>, now: Instant) {
+        if let Some((p, since)) = self.running.take() {
+            self.charge(p, now.saturating_duration_since(since));
+        }
+        self.running = player.map(|p| (p, now));
+    }
+
+    fn charge(&mut self, player: usize, used: Duration) {
+        let remaining = self.remaining[player];
+        match self.control {
+            TimeControl::Fischer { increment, .. } => {
+                self.remaining[player] = if used < remaining {
+                    (remaining - used).saturating_add(increment)
+                } else {
+                    Duration::from_secs(0)
+                };
+            }
+            TimeControl::ByoYomi { period, .. } => {
+                if used <= remaining {
+                    self.remaining[player] = remaining - used;
+                } else {
+                    let over = (used - remaining).as_millis() / period.as_millis().max(1);
+                    let over = u32::try_from(over).unwrap_or(u32::MAX);
+                    self.remaining[player] = Duration::from_secs(0);
+                    self.periods[player] = self.periods[player].saturating_sub(over);
+                }
+            }
+        }
+    }
+
+    /// Player (0 or 1) whose time has run out
+    pub fn flagged(&self, now: Instant) -> Option<usize> {
+        let (player, since) = self.running?;
+        let used = now.saturating_duration_since(since);
+        let limit = match self.control {
+            TimeControl::Fischer { .. } => self.remaining[player],
+            TimeControl::ByoYomi { period, .. } => period
+                .checked_mul(self.periods[player])
+                .and_then(|p| p.checked_add(self.remaining[player]))
+                .unwrap_or(Duration::MAX),
+        };
+        if used >= limit {
+            Some(player)
+        } else {
+            None
+        }
+    }
+
+    pub fn state(&self, now: Instant) -> ClockState {
+        let time = |player: usize| {
+            let used = match self.running {
+                Some((p, since)) if p == player => now.saturating_duration_since(since),
+                _ => Duration::from_secs(0),
+            };
+            PlayerTime {
+                remaining: u64::try_from(self.remaining[player].saturating_sub(used).as_millis())
+                    .unwrap_or(u64::MAX),
+                periods: self.periods[player],
+            }
+        };
+        ClockState {
+            player1: time(0),
+            player2: time(1),
+            running: self.running.map(|(p, _)| p as u8 + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, TimeControl};
+    use std::time::{Duration, Instant};
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            TimeControl::parse("time", "300+5"),
+            Some(TimeControl::Fischer {
+                base: secs(300),
+                increment: secs(5),
+            })
+        );
+        assert_eq!(
+            TimeControl::parse("byoyomi", "600+30x3"),
+            Some(TimeControl::ByoYomi {
+                base: secs(600),
+                period: secs(30),
+                periods: 3,
+            })
+        );
+        assert_eq!(TimeControl::parse("time", "300"), None);
+        assert_eq!(TimeControl::parse("byoyomi", "600+30x0"), None);
+        assert_eq!(TimeControl::parse("clock", "300+5"), None);
+    }
+
+    #[test]
+    fn limits() {
+        assert!(TimeControl::parse("time", "86400+3600").is_some());
+        assert!(TimeControl::parse("byoyomi", "86400+3600x100").is_some());
+        assert_eq!(TimeControl::parse("time", "18446744073709551615+5"), None);
+        assert_eq!(TimeControl::parse("time", "86401+5"), None);
+        assert_eq!(TimeControl::parse("time", "300+3601"), None);
+        assert_eq!(
+            TimeControl::parse("byoyomi", "0+18446744073709551615x2"),
+            None
+        );
+        assert_eq!(TimeControl::parse("byoyomi", "0+3601x2"), None);
+        assert_eq!(TimeControl::parse("byoyomi", "0+30x101"), None);
+        assert_eq!(TimeControl::parse("byoyomi", "0+0x3"), None);
+        assert_eq!(
+            TimeControl::parse("byoyomi", "0+30x18446744073709551615"),
+            None
+        );
+    }
+
+    #[test]
+    fn no_overflow() {
+        // clocks built around the limits, as a bug elsewhere could
+        let t = Instant::now();
+        let mut clock = Clock::new(TimeControl::Fischer {
+            base: Duration::MAX,
+            increment: Duration::MAX,
+        });
+        clock.switch(Some(0), t);
+        clock.switch(Some(1), t + secs(1));
+        assert_eq!(clock.state(t + secs(1)).player1.remaining, u64::MAX);
+
+        let mut clock = Clock::new(TimeControl::ByoYomi {
+            base: Duration::MAX,
+            period: Duration::MAX,
+            periods: u32::MAX,
+        });
+        clock.switch(Some(0), t);
+        assert_eq!(clock.flagged(t + secs(1)), None);
+        clock.switch(Some(1), t + secs(1));
+        assert_eq!(clock.state(t + secs(1)).player1.periods, u32::MAX);
+    }
+
+    #[test]
+    fn fischer() {
+        let t = Instant::now();
+        let mut clock = Clock::new(TimeControl::Fischer {
+            base: secs(60),
+            increment: secs(2),
+        });
+        clock.switch(Some(0), t);
+        clock.switch(Some(1), t + secs(10));
+        let state = clock.state(t + secs(15));
+        assert_eq!(state.player1.remaining, 52_000);
+        assert_eq!(state.player2.remaining, 55_000);
+        assert_eq!(state.running, Some(2));
+        assert_eq!(clock.flagged(t + secs(69)), None);
+        assert_eq!(clock.flagged(t + secs(70)), Some(1));
+        clock.switch(None, t + secs(20));
+        assert_eq!(clock.flagged(t + secs(1000)), None);
+    }
+
+    #[test]
+    fn byo_yomi() {
+        let t = Instant::now();
+        let mut clock = Clock::new(TimeControl::ByoYomi {
+            base: secs(10),
+            period: secs(5),
+            periods: 2,
+        });
+        clock.switch(Some(0), t);
+        // main time and 4 seconds of the first period, no period is used up
+        clock.switch(Some(1), t + secs(14));
+        assert_eq!(clock.state(t + secs(14)).player1.periods, 2);
+        clock.switch(Some(0), t + secs(14));
+        // a period is used up
+        clock.switch(Some(1), t + secs(20));
+        assert_eq!(clock.state(t + secs(20)).player1.periods, 1);
+        clock.switch(Some(0), t + secs(20));
+        assert_eq!(clock.flagged(t + secs(24)), None);
+        assert_eq!(clock.flagged(t + secs(25)), Some(0));
+    }
+}
@@ -0,0 +1,235 @@
+//! Turn-based games for two players that a room can host. `Server` only
+//! talks to the `Game` trait, so a new game type needs an implementation
+//! and an entry in `create`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::any::Any;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::line::LineGame;
+use crate::reversi::Reversi;
+
+/// Names of the game types `create` knows
+pub const KINDS: &[&str] = &["reversi", "connect_four", "gomoku"];
+
+/// New game of the type `kind`, `None` if there is no such game
+pub fn create(kind: &str) -> Option<Box<dyn Game>> {
+    match kind {
+        "reversi" => Some(Box::new(Reversi::new())),
+        "connect_four" => Some(Box::new(LineGame::connect_four())),
+        "gomoku" => Some(Box::new(LineGame::gomoku())),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub enum States {
+    End,
+    TurnPlayer1,
+    TurnPlayer2,
+}
+
+/// Seat of a player, player1 moves first. Clients send it as 1 or 2.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "u8")]
+pub enum Player {
+    One = 1,
+    Two = 2,
+}
+
+impl TryFrom<u8> for Player {
+    type Error = String;
+
+    fn try_from(seat: u8) -> Result<Self, Self::Error> {
+        match seat {
+            1 => Ok(Player::One),
+            2 => Ok(Player::Two),
+            _ => Err(format!("seat must be 1 or 2, not {}", seat)),
+        }
+    }
+}
+
+impl Player {
+    pub fn other(self) -> Player {
+        match self {
+            Player::One => Player::Two,
+            Player::Two => Player::One,
+        }
+    }
+}
+
+/// Reason why a game rejected a move
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SetDiscError {
+    /// (x, y) is outside of the board
+    OutOfBounds,
+    /// A disc is already placed on (x, y), or the column is full in
+    /// Connect Four
+    Occupied,
+    /// The game is not running or the turn belongs to the other player
+    NotYourTurn,
+    /// The move does not flip any disc
+    NoFlips,
+    /// The room has no game table
+    NoGame,
+}
+
+impl fmt::Display for SetDiscError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            SetDiscError::OutOfBounds => "out of bounds",
+            SetDiscError::Occupied => "square is occupied",
+            SetDiscError::NotYourTurn => "not your turn",
+            SetDiscError::NoFlips => "no discs to flip",
+            SetDiscError::NoGame => "no game in this room",
+        };
+        f.write_str(msg)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Winner {
+    Player1,
+    Player2,
+    Draw,
+}
+
+/// Why a game ended
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EndReason {
+    /// the game came to its end by the rules
+    Completed,
+    Resignation,
+    /// both players agreed to a draw
+    Agreement,
+    /// a player left the game
+    Abandonment,
+    /// a player ran out of time
+    Timeout,
+}
+
+/// Final score of a finished game
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct GameResult {
+    pub winner: Winner,
+    /// score of player1, the number of discs in Reversi
+    pub player1: usize,
+    /// score of player2
+    pub player2: usize,
+    pub reason: EndReason,
+}
+
+/// A game between the players seated in `Player::One` and `Player::Two`.
+/// Players are identified by their session id, 0 is nobody.
+/// Methods take `&self`, implementations keep their state in cells.
+pub trait Game {
+    /// name of the game type, one of `KINDS`
+    fn kind(&self) -> &'static str;
+
+    /// id of the player in `seat`, 0 if the seat is empty
+    fn player(&self, seat: Player) -> usize;
+
+    fn set_player(&self, seat: Player, id: usize);
+
+    /// Start a new game, `size` is the board size or `None` for the
+    /// default one. Returns why the settings can't be used.
+    fn start(&self, size: Option<usize>) -> Result<(), String>;
+
+    /// The player `id` puts a piece on (x, y)
+    fn play(&self, id: usize, x: usize, y: usize) -> Result<(), SetDiscError>;
+
+    fn state(&self) -> States;
+
+    /// set once the game is over
+    fn result(&self) -> Option<GameResult>;
+
+    /// Fields of the `update_state` message besides `cmd`, at least
+    /// `state` and `data`, the board
+    fn to_json(&self) -> Map<String, Value>;
+
+    /// The opponent of the player `id` wins by `reason`,
+    /// returns false if no game is running or `id` is not playing
+    fn forfeit(&self, id: usize, reason: EndReason) -> bool;
+
+    /// End the running game as a draw both players agreed to
+    fn agree_draw(&self) -> bool;
+
+    /// true if the player `id` has made a move that `undo` can take back
+    fn can_undo(&self, _id: usize) -> bool {
+        false
+    }
+
+    /// Take back the last move of the player `id` and every move after it.
+    /// Returns false if the player has no move to take back.
+    fn undo(&self, _id: usize) -> bool {
+        false
+    }
+
+    /// for features of a single game type, such as the Reversi AI
+    fn as_any(&self) -> &dyn Any;
+
+    /// The player `id` gives up, returns false if it can't
+    fn resign(&self, id: usize) -> bool {
+        self.forfeit(id, EndReason::Resignation)
+    }
+
+    /// The player `id` left the running game
+    fn abandon(&self, id: usize) -> bool {
+        self.forfeit(id, EndReason::Abandonment)
+    }
+
+    /// The player `id` ran out of time
+    fn time_out(&self, id: usize) -> bool {
+        self.forfeit(id, EndReason::Timeout)
+    }
+
+    /// Seat of the player `id`
+    fn seat_of(&self, id: usize) -> Option<Player> {
+        match id {
+            0 => None,
+            _ if id == self.player(Player::One) => Some(Player::One),
+            _ if id == self.player(Player::Two) => Some(Player::Two),
+            _ => None,
+        }
+    }
+
+    /// id of the player to move, `None` if the game is over
+    fn to_move(&self) -> Option<usize> {
+        match self.state() {
+            States::TurnPlayer1 => Some(self.player(Player::One)),
+            States::TurnPlayer2 => Some(self.player(Player::Two)),
+            States::End => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create, Player, States, KINDS};
+
+    #[test]
+    fn seats() {
+        for kind in KINDS {
+            let game = create(kind).unwrap();
+            assert_eq!(game.kind(), *kind);
+            assert_eq!(game.state(), States::End);
+            game.set_player(Player::One, 3);
+            game.set_player(Player::Two, 5);
+            assert_eq!(game.seat_of(5), Some(Player::Two));
+            assert_eq!(game.seat_of(0), None);
+            assert!(!game.resign(3));
+            game.start(None).unwrap();
+            assert_eq!(game.to_move(), Some(3));
+            assert!(game.to_json().contains_key("data"));
+            assert!(game.resign(3));
+            assert_eq!(game.to_move(), None);
+            assert!(game.result().is_some());
+        }
+        assert!(create("chess").is_none());
+    }
+}
@@ -0,0 +1,107 @@
+//! Invite tokens for rooms. A token carries its room and expiry time and is
+//! signed with a secret of the server, so it can't be forged or changed:
+//! `<id>.<expires>.<s|m>.<room in hex>.<HMAC-SHA256 in hex>`
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Invite {
+    /// random id, used to revoke the token
+    pub id: u64,
+    pub room: String,
+    /// seconds since the UNIX epoch
+    pub expires: u64,
+    /// the token is revoked once it has been used
+    pub single_use: bool,
+}
+
+impl Invite {
+    fn payload(&self) -> String {
+        format!(
+            "{:016x}.{}.{}.{}",
+            self.id,
+            self.expires,
+            if self.single_use { "s" } else { "m" },
+            hex::encode(&self.room)
+        )
+    }
+
+    fn mac(payload: &str, secret: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC takes any key size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    pub fn sign(&self, secret: &[u8]) -> String {
+        let payload = self.payload();
+        let tag = Invite::mac(&payload, secret).finalize().into_bytes();
+        format!("{}.{}", payload, hex::encode(tag))
+    }
+
+    /// Room named in `token`, without checking the signature
+    pub fn room(token: &str) -> Option<String> {
+        let room = token.split('.').nth(3)?;
+        String::from_utf8(hex::decode(room).ok()?).ok()
+    }
+
+    /// Reads a token made by `sign` with the same secret,
+    /// `None` if it is malformed or the signature doesn't match.
+    /// The expiry time is not checked.
+    pub fn verify(token: &str, secret: &[u8]) -> Option<Invite> {
+        let (payload, tag) = token.rsplit_once('.')?;
+        Invite::mac(payload, secret)
+            .verify_slice(&hex::decode(tag).ok()?)
+            .ok()?;
+        let mut parts = payload.split('.');
+        let invite = Invite {
+            id: u64::from_str_radix(parts.next()?, 16).ok()?,
+            expires: parts.next()?.parse().ok()?,
+            single_use: match parts.next()? {
+                "s" => true,
+                "m" => false,
+                _ => return None,
+            },
+            room: String::from_utf8(hex::decode(parts.next()?).ok()?).ok()?,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(invite)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Invite;
+
+    #[test]
+    fn sign_and_verify() {
+        let invite = Invite {
+            id: 42,
+            room: "room.1".to_owned(),
+            expires: 1_792_321_845,
+            single_use: true,
+        };
+        let token = invite.sign(b"secret");
+        assert_eq!(Invite::verify(&token, b"secret"), Some(invite.clone()));
+        assert_eq!(Invite::room(&token), Some("room.1".to_owned()));
+        assert_eq!(Invite::verify(&token, b"other"), None);
+        // another room with the signature of the original
+        let forged = token.replacen(&hex::encode("room.1"), &hex::encode("room.2"), 1);
+        assert_eq!(Invite::verify(&forged, b"secret"), None);
+        assert_eq!(Invite::verify("", b"secret"), None);
+        assert_eq!(Invite::verify("a.b", b"secret"), None);
+        let multi = Invite {
+            single_use: false,
+            ..invite
+        };
+        assert!(
+            !Invite::verify(&multi.sign(b"secret"), b"secret")
+                .unwrap()
+                .single_use
+        );
+    }
+}
@@ -1,2 +1,9 @@
+pub mod ai;
+pub mod clock;
+pub mod game;
+pub mod invite;
+pub mod line;
+pub mod password;
+pub mod protocol;
 pub mod server;
 pub mod reversi;
\ No newline at end of file
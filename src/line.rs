@@ -0,0 +1,329 @@
+//! Games won by the first line of stones: Connect Four, where stones drop to
+//! the lowest empty square of a 7x6 board and four in a row wins, and Gomoku,
+//! where five in a row wins on a 15x15 board.
+
+use serde_json::{json, Map, Value};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+
+use crate::game::{EndReason, Game, GameResult, Player, SetDiscError, States, Winner};
+
+/// Stones in the board data, the same values as the discs of Reversi
+const PLAYER1: u8 = 1;
+const PLAYER2: u8 = 2;
+
+pub const GOMOKU_SIZE: usize = 15;
+/// Gomoku board sizes `start` accepts
+pub const GOMOKU_MIN_SIZE: usize = 9;
+pub const GOMOKU_MAX_SIZE: usize = 19;
+
+#[derive(Debug)]
+pub struct LineGame {
+    kind: &'static str,
+    width: Cell<usize>,
+    height: Cell<usize>,
+    /// stones in a row needed to win
+    length: usize,
+    /// stones drop to the bottom of the column, the y of a move is ignored
+    gravity: bool,
+    pub player1_id: Cell<usize>,
+    pub player2_id: Cell<usize>,
+    state: RefCell<States>,
+    /// `board[y][x]`, 0 is empty
+    board: RefCell<Vec<Vec<u8>>>,
+    /// (x, y) of every stone since the start, player1 made the even ones
+    moves: RefCell<Vec<(usize, usize)>>,
+    result: RefCell<Option<GameResult>>,
+}
+
+impl LineGame {
+    fn new(kind: &'static str, width: usize, height: usize, length: usize, gravity: bool) -> Self {
+        LineGame {
+            kind,
+            width: Cell::new(width),
+            height: Cell::new(height),
+            length,
+            gravity,
+            player1_id: Cell::new(0),
+            player2_id: Cell::new(0),
+            state: RefCell::new(States::End),
+            board: RefCell::new(vec![vec![0; width]; height]),
+            moves: RefCell::new(Vec::new()),
+            result: RefCell::new(None),
+        }
+    }
+
+    pub fn connect_four() -> Self {
+        LineGame::new("connect_four", 7, 6, 4, true)
+    }
+
+    pub fn gomoku() -> Self {
+        LineGame::new("gomoku", GOMOKU_SIZE, GOMOKU_SIZE, 5, false)
+    }
+
+    /// true if the stone on (x, y) is part of a line long enough to win
+    fn wins(&self, board: &[Vec<u8>], x: usize, y: usize) -> bool {
+        let stone = board[y][x];
+        let (width, height) = (self.width.get() as isize, self.height.get() as isize);
+        let run = |dx: isize, dy: isize| {
+            let (mut x, mut y, mut n) = (x as isize + dx, y as isize + dy, 0);
+            while (0..width).contains(&x)
+                && (0..height).contains(&y)
+                && board[y as usize][x as usize] == stone
+            {
+                n += 1;
+                x += dx;
+                y += dy;
+            }
+            n
+        };
+        [(1, 0), (0, 1), (1, 1), (1, -1)]
+            .iter()
+            .any(|&(dx, dy)| 1 + run(dx, dy) + run(-dx, -dy) >= self.length)
+    }
+
+    /// End the running game with `winner`, returns false if no game is running
+    fn finish(&self, winner: Winner, reason: EndReason) -> bool {
+        if *self.state.borrow() == States::End {
+            return false;
+        }
+        let moves = self.moves.borrow().len();
+        self.state.replace(States::End);
+        self.result.replace(Some(GameResult {
+            winner,
+            player1: moves.div_ceil(2),
+            player2: moves / 2,
+            reason,
+        }));
+        true
+    }
+
+    pub fn last_move(&self) -> Option<(usize, usize)> {
+        self.moves.borrow().last().cloned()
+    }
+}
+
+impl Game for LineGame {
+    fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    fn player(&self, seat: Player) -> usize {
+        match seat {
+            Player::One => self.player1_id.get(),
+            Player::Two => self.player2_id.get(),
+        }
+    }
+
+    fn set_player(&self, seat: Player, id: usize) {
+        match seat {
+            Player::One => self.player1_id.set(id),
+            Player::Two => self.player2_id.set(id),
+        }
+    }
+
+    fn start(&self, size: Option<usize>) -> Result<(), String> {
+        if self.gravity {
+            if size.is_some() {
+                return Err(format!("{} is played on a fixed board", self.kind));
+            }
+        } else {
+            let size = size.unwrap_or(GOMOKU_SIZE);
+            if !(GOMOKU_MIN_SIZE..=GOMOKU_MAX_SIZE).contains(&size) {
+                return Err(format!(
+                    "size must be from {} to {}",
+                    GOMOKU_MIN_SIZE, GOMOKU_MAX_SIZE
+                ));
+            }
+            self.width.set(size);
+            self.height.set(size);
+        }
+        self.board
+            .replace(vec![vec![0; self.width.get()]; self.height.get()]);
+        self.moves.borrow_mut().clear();
+        self.result.replace(None);
+        self.state.replace(States::TurnPlayer1);
+        Ok(())
+    }
+
+    fn play(&self, id: usize, x: usize, y: usize) -> Result<(), SetDiscError> {
+        if x >= self.width.get() || (!self.gravity && y >= self.height.get()) {
+            return Err(SetDiscError::OutOfBounds);
+        }
+        let (stone, next) = match (self.state(), self.seat_of(id)) {
+            (States::TurnPlayer1, Some(Player::One)) => (PLAYER1, States::TurnPlayer2),
+            (States::TurnPlayer2, Some(Player::Two)) => (PLAYER2, States::TurnPlayer1),
+            _ => return Err(SetDiscError::NotYourTurn),
+        };
+        let mut board = self.board.borrow_mut();
+        let y = if self.gravity {
+            (0..self.height.get())
+                .rev()
+                .find(|&y| board[y][x] == 0)
+                .ok_or(SetDiscError::Occupied)?
+        } else if board[y][x] != 0 {
+            return Err(SetDiscError::Occupied);
+        } else {
+            y
+        };
+        board[y][x] = stone;
+        self.moves.borrow_mut().push((x, y));
+        let winner = if stone == PLAYER1 {
+            Winner::Player1
+        } else {
+            Winner::Player2
+        };
+        if self.wins(&board, x, y) {
+            self.finish(winner, EndReason::Completed);
+        } else if self.moves.borrow().len() == self.width.get() * self.height.get() {
+            self.finish(Winner::Draw, EndReason::Completed);
+        } else {
+            self.state.replace(next);
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> States {
+        self.state.borrow().clone()
+    }
+
+    fn result(&self) -> Option<GameResult> {
+        self.result.borrow().clone()
+    }
+
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert("state".to_owned(), json!(self.state()));
+        map.insert("last_move".to_owned(), json!(self.last_move()));
+        map.insert("data".to_owned(), json!(*self.board.borrow()));
+        map
+    }
+
+    fn forfeit(&self, id: usize, reason: EndReason) -> bool {
+        let winner = match self.seat_of(id) {
+            Some(Player::One) => Winner::Player2,
+            Some(Player::Two) => Winner::Player1,
+            None => return false,
+        };
+        self.finish(winner, reason)
+    }
+
+    fn agree_draw(&self) -> bool {
+        self.finish(Winner::Draw, EndReason::Agreement)
+    }
+
+    fn can_undo(&self, id: usize) -> bool {
+        let first = match self.seat_of(id) {
+            Some(Player::One) => 0,
+            Some(Player::Two) => 1,
+            None => return false,
+        };
+        self.moves.borrow().len() > first
+    }
+
+    fn undo(&self, id: usize) -> bool {
+        if !self.can_undo(id) {
+            return false;
+        }
+        let seat = self.seat_of(id);
+        let mut moves = self.moves.borrow_mut();
+        let mut board = self.board.borrow_mut();
+        while let Some((x, y)) = moves.pop() {
+            board[y][x] = 0;
+            let mover = if moves.len().is_multiple_of(2) {
+                Player::One
+            } else {
+                Player::Two
+            };
+            if Some(mover) == seat {
+                break;
+            }
+        }
+        self.state.replace(if moves.len().is_multiple_of(2) {
+            States::TurnPlayer1
+        } else {
+            States::TurnPlayer2
+        });
+        self.result.replace(None);
+        true
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineGame;
+    use crate::game::{EndReason, Game, Player, SetDiscError, States, Winner};
+    use serde_json::json;
+
+    fn seated(game: LineGame) -> LineGame {
+        game.set_player(Player::One, 1);
+        game.set_player(Player::Two, 2);
+        game.start(None).unwrap();
+        game
+    }
+
+    #[test]
+    fn connect_four() {
+        let g = seated(LineGame::connect_four());
+        assert!(g.start(Some(8)).is_err());
+        // stones drop, the y of the move doesn't matter
+        g.play(1, 3, 0).unwrap();
+        assert_eq!(g.last_move(), Some((3, 5)));
+        g.play(2, 3, 5).unwrap();
+        assert_eq!(g.last_move(), Some((3, 4)));
+        // player1's stones are 1 like its discs in Reversi
+        let data = &g.to_json()["data"];
+        assert_eq!((&data[5][3], &data[4][3]), (&json!(1), &json!(2)));
+        assert_eq!(g.play(2, 0, 0), Err(SetDiscError::NotYourTurn));
+        assert_eq!(g.play(1, 7, 0), Err(SetDiscError::OutOfBounds));
+        for x in 0..3 {
+            g.play(1, x, 0).unwrap();
+            if x < 2 {
+                g.play(2, x, 0).unwrap();
+            }
+        }
+        // 0..=3 on the bottom row
+        let result = g.result().unwrap();
+        assert_eq!(result.winner, Winner::Player1);
+        assert_eq!(result.reason, EndReason::Completed);
+        assert_eq!(g.state(), States::End);
+
+        let g = seated(LineGame::connect_four());
+        for i in 0..6 {
+            g.play(if i % 2 == 0 { 1 } else { 2 }, 0, 0).unwrap();
+        }
+        assert_eq!(g.play(1, 0, 0), Err(SetDiscError::Occupied));
+    }
+
+    #[test]
+    fn gomoku() {
+        let g = seated(LineGame::gomoku());
+        assert_eq!(g.to_json()["data"].as_array().unwrap().len(), 15);
+        for i in 0..4 {
+            g.play(1, i, i).unwrap();
+            g.play(2, i, 10).unwrap();
+        }
+        assert_eq!(g.play(1, 0, 0), Err(SetDiscError::Occupied));
+        assert_eq!(g.play(1, 15, 0), Err(SetDiscError::OutOfBounds));
+
+        // take back the last move of player1 and player2's reply
+        assert!(g.undo(1));
+        assert_eq!(g.last_move(), Some((2, 10)));
+        assert_eq!(g.state(), States::TurnPlayer1);
+        g.play(1, 3, 3).unwrap();
+        g.play(2, 3, 10).unwrap();
+
+        g.play(1, 4, 4).unwrap();
+        assert_eq!(g.result().unwrap().winner, Winner::Player1);
+        assert_eq!(g.play(2, 4, 10), Err(SetDiscError::NotYourTurn));
+
+        let g = seated(LineGame::gomoku());
+        g.start(Some(19)).unwrap();
+        assert_eq!(g.to_json()["data"].as_array().unwrap().len(), 19);
+        assert!(g.start(Some(5)).is_err());
+    }
+}
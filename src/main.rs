@@ -1,3 +1,4 @@
+use std::net::IpAddr;
 use std::time::{Duration, Instant};
 
 use actix::*;
@@ -5,19 +6,28 @@ use actix_files as fs;
 use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
 
+use serde::Deserialize;
 use serde_json::json;
 
-use ws_room_test::server;
+use ws_room_test::protocol::{self, ErrorCode, Request, RequestId, Response};
+use ws_room_test::{ai, clock, invite, server};
 
 /// How often hertbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 /// How long before lack of client response causes a timeout
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+#[derive(Deserialize)]
+struct WsQuery {
+    /// token from `/invite`, joins its room on connect
+    invite: Option<String>,
+}
+
 /// Entry point for our route
 fn ws_route(
     req: HttpRequest,
     stream: web::Payload,
+    query: web::Query<WsQuery>,
     srv: web::Data<Addr<server::Server>>,
 ) -> Result<HttpResponse, Error> {
     ws::start(
@@ -26,6 +36,8 @@ fn ws_route(
             hb: Instant::now(),
             room: "Main".to_owned(),
             name: None,
+            invite: query.into_inner().invite,
+            peer: req.peer_addr().map(|a| a.ip()),
             addr: srv.get_ref().clone(),
         },
         &req,
@@ -33,6 +45,14 @@ fn ws_route(
     )
 }
 
+/// Error reply to `request` with the id `id` when the server didn't answer
+fn internal_error(request: &'static str, id: Option<&RequestId>) -> String {
+    println!("Server did not answer {}", request);
+    protocol::Error::new(ErrorCode::Internal, "the server didn't answer")
+        .of(request)
+        .to_json(id)
+}
+
 struct WsSession {
     /// unique session id
     id: usize,
@@ -43,6 +63,10 @@ struct WsSession {
     room: String,
     /// peer name
     name: Option<String>,
+    /// invite given on connect, used once the session is registered
+    invite: Option<String>,
+    /// address of the client, a proxy's if there is one in front
+    peer: Option<IpAddr>,
     /// server
     addr: Addr<server::Server>,
 }
@@ -66,12 +90,28 @@ impl Actor for WsSession {
         let addr = ctx.address();
         self.addr
             .send(server::Connect {
-                addr: addr.recipient(),
+                addr: addr.clone().recipient(),
+                joined: addr.recipient(),
+                peer: self.peer,
             })
             .into_actor(self)
             .then(|res, act, ctx| {
                 match res {
-                    Ok(res) => act.id = res,
+                    Ok(res) => {
+                        act.id = res;
+                        if let Some(token) = act.invite.take() {
+                            match invite::Invite::room(&token) {
+                                Some(room) => {
+                                    let options = server::RoomOptions::default();
+                                    act.send_join(room, options, false, Some(token), None, ctx);
+                                }
+                                None => ctx.text(
+                                    protocol::Error::rejected("join", "invalid invite")
+                                        .to_json(None),
+                                ),
+                            }
+                        }
+                    }
                     // something is wrong with server
                     _ => ctx.stop(),
                 }
@@ -96,35 +136,185 @@ impl Handler<server::Message> for WsSession {
     }
 }
 
-impl WsSession {
-    fn room(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+/// Entered a room from its waiting list
+impl Handler<server::Joined> for WsSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: server::Joined, ctx: &mut Self::Context) {
+        self.room = msg.room;
         ctx.text(
-            json!({
-                "cmd": "room",
-                "data": self.room
-            })
-            .to_string(),
+            Response::Join {
+                data: self.room.clone(),
+            }
+            .to_json(None),
         );
     }
-    fn list(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+}
+
+impl WsSession {
+    fn handle_request(
+        &mut self,
+        request: Request,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        match request {
+            Request::Room => ctx.text(
+                Response::Room {
+                    data: self.room.clone(),
+                }
+                .to_json(request_id.as_ref()),
+            ),
+            Request::List {
+                open_seats,
+                in_progress,
+                offset,
+                limit,
+            } => self.list(
+                server::ListRooms {
+                    id: self.id,
+                    open_seats,
+                    in_progress,
+                    offset,
+                    limit,
+                },
+                request_id,
+                ctx,
+            ),
+            Request::Join {
+                room,
+                options,
+                queue,
+                invite,
+            } => self.send_join(room, options, queue, invite, request_id, ctx),
+            Request::Name { name } => self.name(name),
+            Request::Members => self.members(request_id, ctx),
+            Request::Start { size, time } => self.start(size, time, request_id, ctx),
+            Request::GetPlayer { seat } => self.get_player(seat, request_id, ctx),
+            Request::Sit { seat } => self.regist_player(seat, None, request_id, ctx),
+            Request::Bot { seat, level } => self.regist_player(seat, Some(level), request_id, ctx),
+            Request::PutDisc { x, y } => self.put_disc(x, y, request_id, ctx),
+            Request::Hints => self.hints(request_id, ctx),
+            Request::History => self.history(request_id, ctx),
+            Request::ChatHistory { before } => self.chat_history(before, request_id, ctx),
+            Request::Undo { action } => {
+                let msg = server::Undo {
+                    room: self.room.clone(),
+                    id: self.id,
+                    action,
+                };
+                self.send_request("undo", msg, request_id, ctx)
+            }
+            Request::Resign => {
+                let msg = server::Resign {
+                    room: self.room.clone(),
+                    id: self.id,
+                };
+                self.send_request("resign", msg, request_id, ctx)
+            }
+            Request::Draw { action } => {
+                let msg = server::Draw {
+                    room: self.room.clone(),
+                    id: self.id,
+                    action,
+                };
+                self.send_request("draw", msg, request_id, ctx)
+            }
+            Request::Invite { single_use, ttl } => self.invite(single_use, ttl, request_id, ctx),
+            Request::RevokeInvite { token } => {
+                let msg = server::RevokeInvite {
+                    room: self.room.clone(),
+                    id: self.id,
+                    token,
+                };
+                self.send_request("revoke_invite", msg, request_id, ctx)
+            }
+            Request::Kick { name } => self.kick("kick", name, false, request_id, ctx),
+            Request::Ban { name } => self.kick("ban", name, true, request_id, ctx),
+            Request::Unban { name } => {
+                let msg = server::Unban {
+                    room: self.room.clone(),
+                    id: self.id,
+                    target: name,
+                };
+                self.send_request("unban", msg, request_id, ctx)
+            }
+            Request::Moderator { name, moderator } => {
+                let msg = server::Moderator {
+                    room: self.room.clone(),
+                    id: self.id,
+                    target: name,
+                    moderator,
+                };
+                self.send_request("moderator", msg, request_id, ctx)
+            }
+            Request::Say { text } => self.say(text),
+            Request::PrivateMessage { to, text } => {
+                let msg = server::PrivateMessage {
+                    id: self.id,
+                    to,
+                    text,
+                };
+                self.send_request("private_message", msg, request_id, ctx)
+            }
+        }
+    }
+    /// Send a server message that succeeds or fails with a message and
+    /// reply with its outcome
+    fn send_request<M>(
+        &mut self,
+        request: &'static str,
+        msg: M,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) where
+        M: actix::Message<Result = Result<String, String>> + Send + 'static,
+        server::Server: Handler<M>,
+    {
+        self.addr
+            .send(msg)
+            .into_actor(self)
+            .then(move |res, _, ctx| {
+                match res {
+                    Ok(Ok(data)) => ctx.text(
+                        Response::Done {
+                            request,
+                            data: Some(data),
+                        }
+                        .to_json(request_id.as_ref()),
+                    ),
+                    Ok(Err(e)) => {
+                        ctx.text(protocol::Error::rejected(request, e).to_json(request_id.as_ref()))
+                    }
+                    Err(_) => ctx.text(internal_error(request, request_id.as_ref())),
+                }
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+    fn list(
+        &mut self,
+        msg: server::ListRooms,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
         // Send ListRooms message to server and wait for
         // response
         println!("List rooms");
         self.addr
-            .send(server::ListRooms)
+            .send(msg)
             .into_actor(self)
-            .then(|res, _, ctx| {
+            .then(move |res, _, ctx| {
                 match res {
-                    Ok(rooms) => {
-                        ctx.text(
-                            json!({
-                                "cmd": "list",
-                                "data": &rooms
-                            })
-                            .to_string(),
-                        );
-                    }
-                    _ => println!("Something is wrong"),
+                    Ok(list) => ctx.text(
+                        Response::List {
+                            data: list.rooms,
+                            total: list.total,
+                            offset: list.offset,
+                        }
+                        .to_json(request_id.as_ref()),
+                    ),
+                    Err(_) => ctx.text(internal_error("list", request_id.as_ref())),
                 }
                 fut::ok(())
             })
@@ -133,162 +323,330 @@ impl WsSession {
         // so actor wont receive any new message until it get list
         // of rooms back
     }
-    fn join(&mut self, v: Vec<&str>, ctx: &mut ws::WebsocketContext<Self>) {
-        if v.len() == 2 {
-            self.room = v[1].to_owned();
-            self.addr.do_send(server::Join {
+    fn send_join(
+        &mut self,
+        room: String,
+        options: server::RoomOptions,
+        queue: bool,
+        invite: Option<String>,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.addr
+            .send(server::Join {
                 id: self.id,
-                name: self.room.clone(),
-            });
-
-            ctx.text(
-                json!({
-                    "cmd": "join",
-                    "data": self.room.clone(),
-                })
-                .to_string(),
-            );
-        } else {
-            ctx.text("!!! room name is required");
-        }
+                name: room.clone(),
+                options,
+                queue,
+                invite,
+            })
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                match res {
+                    Ok(Ok(server::JoinStatus::Joined)) => {
+                        act.room = room;
+                        ctx.text(
+                            Response::Join {
+                                data: act.room.clone(),
+                            }
+                            .to_json(request_id.as_ref()),
+                        );
+                    }
+                    Ok(Ok(server::JoinStatus::Queued(position))) => {
+                        ctx.text(Response::Queued { data: position }.to_json(request_id.as_ref()))
+                    }
+                    Ok(Err(e)) => {
+                        ctx.text(protocol::Error::rejected("join", e).to_json(request_id.as_ref()))
+                    }
+                    Err(_) => ctx.text(internal_error("join", request_id.as_ref())),
+                }
+                fut::ok(())
+            })
+            .wait(ctx);
     }
-    fn name(&mut self, v: Vec<&str>, ctx: &mut ws::WebsocketContext<Self>) {
-        if v.len() == 2 {
-            self.name = Some(v[1].to_owned());
-            self.addr.do_send(server::SetName {
-                id: self.id,
-                new_name: self.name.clone().unwrap_or("名無し".to_owned()),
-            });
-        } else {
-            ctx.text("!!! name is required");
-        }
+    fn name(&mut self, name: String) {
+        self.name = Some(name);
+        self.addr.do_send(server::SetName {
+            id: self.id,
+            new_name: self.name.clone().unwrap_or("名無し".to_owned()),
+        });
     }
-    fn members(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+    fn members(&mut self, request_id: Option<RequestId>, ctx: &mut ws::WebsocketContext<Self>) {
         self.addr
-            .send(server::GetMemberNames {
+            .send(server::GetMembers {
                 room: self.room.clone(),
             })
             .into_actor(self)
-            .then(|res, _, ctx| {
+            .then(move |res, _, ctx| {
                 match res {
                     Ok(members) => {
-                        ctx.text(
-                            json!({
-                                "cmd": "members",
-                                "data": &members
-                            })
-                            .to_string(),
-                        );
+                        ctx.text(Response::Members { data: members }.to_json(request_id.as_ref()))
                     }
-                    _ => println!("Something is wrong"),
+                    Err(_) => ctx.text(internal_error("members", request_id.as_ref())),
                 }
                 fut::ok(())
             })
             .wait(ctx);
     }
-    fn start(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+    fn start(
+        &mut self,
+        size: Option<usize>,
+        time: Option<protocol::TimeSetting>,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let time = match time.map(|t| t.control()) {
+            Some(None) => {
+                let message = format!(
+                    "main time must be at most {}s, increments and periods at most {}s \
+                     and byo-yomi needs 1 to {} periods",
+                    clock::MAX_BASE.as_secs(),
+                    clock::MAX_PERIOD.as_secs(),
+                    clock::MAX_PERIODS
+                );
+                let error = protocol::Error::new(ErrorCode::InvalidParameter, &message);
+                ctx.text(error.of("start").to_json(request_id.as_ref()));
+                return;
+            }
+            Some(control) => control,
+            None => None,
+        };
         self.addr
             .send(server::Start {
                 room: self.room.clone(),
+                size,
+                time,
             })
             .into_actor(self)
-            .then(|res, _, ctx| {
+            .then(move |res, _, ctx| {
                 match res {
-                    Ok(r) => {
-                        let json = match r {
-                            Ok(_) => json!({
-                                "cmd": "start_result",
-                                "result": "success",
-                            }),
-                            Err(r) => json!({
-                                "cmd": "start_result",
-                                "result": "failed",
-                                "data": r,
-                            }),
-                        };
-                        ctx.text(json.to_string());
+                    Ok(Ok(_)) => ctx.text(
+                        Response::Done {
+                            request: "start",
+                            data: None,
+                        }
+                        .to_json(request_id.as_ref()),
+                    ),
+                    Ok(Err(e)) => {
+                        ctx.text(protocol::Error::rejected("start", e).to_json(request_id.as_ref()))
                     }
-                    _ => println!("Something is wrong"),
+                    Err(_) => ctx.text(internal_error("start", request_id.as_ref())),
                 }
                 fut::ok(())
             })
             .wait(ctx);
     }
-    fn player(&mut self, v: Vec<&str>, p: server::Player, ctx: &mut ws::WebsocketContext<Self>) {
-        if v.len() == 2 {
-            match v[1] {
-                "get" => {
-                    self.addr
-                        .send(server::GetPlayer {
-                            room: self.room.clone(),
-                            player: p.clone(),
-                        })
-                        .into_actor(self)
-                        .then(move |res, _, ctx| {
-                            match res {
-                                Ok(r) => {
-                                    ctx.text(
-                                        json!({
-                                            "cmd": "player",
-                                            "sub_cmd": "get",
-                                            "data": json!({
-                                                "p": p.clone() as usize,
-                                                "data": r
-                                            }),
-                                        })
-                                        .to_string(),
-                                    );
-                                }
-                                _ => println!("Something is wrong"),
-                            }
-                            fut::ok(())
-                        })
-                        .wait(ctx);
+    fn get_player(
+        &mut self,
+        p: server::Player,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.addr
+            .send(server::GetPlayer {
+                room: self.room.clone(),
+                player: p,
+            })
+            .into_actor(self)
+            .then(move |res, _, ctx| {
+                match res {
+                    Ok(name) => ctx.text(
+                        Response::Player {
+                            seat: p as usize,
+                            data: name,
+                        }
+                        .to_json(request_id.as_ref()),
+                    ),
+                    Err(_) => ctx.text(internal_error("get_player", request_id.as_ref())),
                 }
-                "regist" => {
-                    self.addr.send(server::RegistPlayer {
-                        room: self.room.clone(),
-                        id: self.id,
-                        player: p.clone(),
-                    }).into_actor(self)
-                    .then(move |res, _, ctx| {
-                        match res {
-                            Ok(r) => {
-                                if r {
-                                    ctx.text(json!({
-                                        "cmd": "registered_you",
-                                        "data": p.clone() as usize,
-                                    }).to_string());
-                                }
-                            }
-                            _ => println!("Something is wrong"),
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+    /// Take the seat `p`, or give it to a bot of `level`
+    fn regist_player(
+        &mut self,
+        p: server::Player,
+        bot: Option<ai::Level>,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let request = if bot.is_some() { "bot" } else { "sit" };
+        self.addr
+            .send(server::RegistPlayer {
+                room: self.room.clone(),
+                id: self.id,
+                player: p,
+                bot,
+            })
+            .into_actor(self)
+            .then(move |res, _, ctx| {
+                match res {
+                    Ok(true) if bot.is_some() => ctx.text(
+                        Response::Done {
+                            request,
+                            data: None,
                         }
-                        fut::ok(())
-                    }).wait(ctx);
+                        .to_json(request_id.as_ref()),
+                    ),
+                    Ok(true) => ctx.text(
+                        Response::RegisteredYou { data: p as usize }.to_json(request_id.as_ref()),
+                    ),
+                    Ok(false) => ctx.text(
+                        protocol::Error::rejected(request, "the seat can't be taken")
+                            .to_json(request_id.as_ref()),
+                    ),
+                    Err(_) => ctx.text(internal_error(request, request_id.as_ref())),
                 }
-                _ => println!("command required"),
-            }
-        }
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+    fn hints(&mut self, request_id: Option<RequestId>, ctx: &mut ws::WebsocketContext<Self>) {
+        self.addr
+            .send(server::GetLegalMoves {
+                room: self.room.clone(),
+            })
+            .into_actor(self)
+            .then(move |res, _, ctx| {
+                match res {
+                    Ok(moves) => {
+                        ctx.text(Response::Hints { data: moves }.to_json(request_id.as_ref()))
+                    }
+                    Err(_) => ctx.text(internal_error("hints", request_id.as_ref())),
+                }
+                fut::ok(())
+            })
+            .wait(ctx);
     }
-    fn put_disc(&mut self, v: Vec<&str>, ctx: &mut ws::WebsocketContext<Self>) {
-        if v.len() == 3 {
-            let f = |str:&str| -> bool {
-                match str.parse::<usize>() {
-                    Ok(_) => true,
-                    Err(_) => false,
+    fn history(&mut self, request_id: Option<RequestId>, ctx: &mut ws::WebsocketContext<Self>) {
+        self.addr
+            .send(server::GetHistory {
+                room: self.room.clone(),
+            })
+            .into_actor(self)
+            .then(move |res, _, ctx| {
+                match res {
+                    Ok(history) => {
+                        ctx.text(Response::History { data: history }.to_json(request_id.as_ref()))
+                    }
+                    Err(_) => ctx.text(internal_error("history", request_id.as_ref())),
                 }
-            };
-            if f(v[1]) && f(v[2]) {
-                let (x, y): (usize, usize) = (v[1].parse().unwrap(), v[2].parse().unwrap());
-                self.addr.do_send(server::PutDisc {
-                    room: self.room.clone(),
-                    id: self.id,
-                    x, y
-                });
-            } else {
-                ctx.text("invalid parameter");
-            }
-        }
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+    fn chat_history(
+        &mut self,
+        before: Option<u64>,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.addr
+            .send(server::GetChat {
+                room: self.room.clone(),
+                before,
+            })
+            .into_actor(self)
+            .then(move |res, _, ctx| {
+                match res {
+                    Ok(chat) => {
+                        ctx.text(Response::ChatHistory { data: chat }.to_json(request_id.as_ref()))
+                    }
+                    Err(_) => ctx.text(internal_error("chat_history", request_id.as_ref())),
+                }
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+    fn kick(
+        &mut self,
+        request: &'static str,
+        target: String,
+        ban: bool,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let msg = server::Kick {
+            room: self.room.clone(),
+            id: self.id,
+            target,
+            ban,
+        };
+        self.send_request(request, msg, request_id, ctx)
+    }
+    fn invite(
+        &mut self,
+        single_use: bool,
+        ttl: Option<u64>,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.addr
+            .send(server::CreateInvite {
+                room: self.room.clone(),
+                id: self.id,
+                single_use,
+                ttl: ttl.map_or(server::INVITE_TTL, Duration::from_secs),
+            })
+            .into_actor(self)
+            .then(move |res, _, ctx| {
+                match res {
+                    Ok(Ok(token)) => {
+                        ctx.text(Response::Invite { data: token }.to_json(request_id.as_ref()))
+                    }
+                    Ok(Err(e)) => ctx
+                        .text(protocol::Error::rejected("invite", e).to_json(request_id.as_ref())),
+                    Err(_) => ctx.text(internal_error("invite", request_id.as_ref())),
+                }
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+    fn put_disc(
+        &mut self,
+        x: usize,
+        y: usize,
+        request_id: Option<RequestId>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.addr
+            .send(server::PutDisc {
+                room: self.room.clone(),
+                id: self.id,
+                x,
+                y,
+            })
+            .into_actor(self)
+            .then(move |res, _, ctx| {
+                match res {
+                    Ok(Ok(_)) => ctx.text(
+                        Response::Done {
+                            request: "put_disc",
+                            data: None,
+                        }
+                        .to_json(request_id.as_ref()),
+                    ),
+                    Ok(Err(e)) => {
+                        let mut error =
+                            protocol::Error::new(ErrorCode::IllegalMove, &e).of("put_disc");
+                        error.data = Some(json!(e));
+                        ctx.text(error.to_json(request_id.as_ref()));
+                    }
+                    Err(_) => ctx.text(internal_error("put_disc", request_id.as_ref())),
+                }
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+    /// chat to the room
+    fn say(&mut self, text: String) {
+        // send message to server
+        self.addr.do_send(server::ClientMessage {
+            id: self.id,
+            msg: text,
+            room: self.room.clone(),
+        })
     }
 }
 
@@ -304,37 +662,11 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for WsSession {
             ws::Message::Pong(_) => {
                 self.hb = Instant::now();
             }
-            ws::Message::Text(text) => {
-                let m = text.trim();
-                // we check for /sss type of messages
-                if m.starts_with('/') {
-                    let v: Vec<&str> = m.split_whitespace().collect();
-                    match v[0] {
-                        "/room" => self.room(ctx),
-                        "/list" => self.list(ctx),
-                        "/join" => self.join(v, ctx),
-                        "/name" => self.name(v, ctx),
-                        "/members" => self.members(ctx),
-                        "/start" => self.start(ctx),
-                        "/player1" => self.player(v, server::Player::One, ctx),
-                        "/player2" => self.player(v, server::Player::Two, ctx),
-                        "/put_disc" => self.put_disc(v, ctx),
-                        _ => ctx.text(format!("!!! unknown command: {:?}", m)),
-                    }
-                } else {
-                    let msg = if let Some(ref name) = self.name {
-                        format!("{}: {}", name, m)
-                    } else {
-                        m.to_owned()
-                    };
-                    // send message to server
-                    self.addr.do_send(server::ClientMessage {
-                        id: self.id,
-                        msg,
-                        room: self.room.clone(),
-                    })
-                }
-            }
+            // JSON requests, /sss type of commands or chat
+            ws::Message::Text(text) => match Request::parse(&text) {
+                (request_id, Ok(request)) => self.handle_request(request, request_id, ctx),
+                (request_id, Err(e)) => ctx.text(e.to_json(request_id.as_ref())),
+            },
             ws::Message::Binary(_) => println!("Unexpected binary"),
             ws::Message::Close(_) => {
                 ctx.stop();
@@ -374,8 +706,14 @@ fn main() -> std::io::Result<()> {
     env_logger::init();
     let sys = System::new("ws-example");
 
-    // Start server actor
-    let server = server::Server::default().start();
+    // Start server actor, empty rooms are kept for ROOM_IDLE_SECS
+    let idle = std::env::var("ROOM_IDLE_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map_or(server::ROOM_IDLE_TIMEOUT, Duration::from_secs);
+    let server = server::Server::default()
+        .with_room_idle_timeout(idle)
+        .start();
 
     // Create Http server with websocket support
     HttpServer::new(move || {
@@ -0,0 +1,129 @@
+//! Salted password hashes, the password itself is never stored.
+//! Passwords are stretched with PBKDF2-HMAC-SHA256 so a leaked hash is
+//! slow to brute-force. That makes hashing slow for the server too, so
+//! `Hasher` does it on its own threads.
+
+use actix::prelude::*;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// PBKDF2 rounds of new hashes
+const ITERATIONS: u32 = 100_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasswordHash {
+    salt: [u8; 16],
+    /// kept with the hash so the count can be raised later
+    iterations: u32,
+    hash: [u8; 32],
+}
+
+impl PasswordHash {
+    pub fn new(password: &str) -> Self {
+        Self::with_iterations(password, ITERATIONS)
+    }
+
+    fn with_iterations(password: &str, iterations: u32) -> Self {
+        let salt = rand::thread_rng().gen::<[u8; 16]>();
+        PasswordHash {
+            salt,
+            iterations,
+            hash: pbkdf2(password, &salt, iterations),
+        }
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        constant_time_eq(&pbkdf2(password, &self.salt, self.iterations), &self.hash)
+    }
+}
+
+/// Hashes and checks passwords off the `Server` actor
+pub struct Hasher;
+
+impl Actor for Hasher {
+    type Context = SyncContext<Self>;
+}
+
+/// Hash a password with a new salt
+pub struct Hash(pub String);
+impl Message for Hash {
+    type Result = PasswordHash;
+}
+impl Handler<Hash> for Hasher {
+    type Result = MessageResult<Hash>;
+
+    fn handle(&mut self, msg: Hash, _: &mut Self::Context) -> Self::Result {
+        MessageResult(PasswordHash::new(&msg.0))
+    }
+}
+
+/// Check a password against `hash`
+#[derive(Message)]
+#[rtype(bool)]
+pub struct Verify {
+    pub hash: PasswordHash,
+    pub password: String,
+}
+impl Handler<Verify> for Hasher {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Verify, _: &mut Self::Context) -> Self::Result {
+        msg.hash.verify(&msg.password)
+    }
+}
+
+/// The first 32 byte block of PBKDF2-HMAC-SHA256 (RFC 8018)
+fn pbkdf2(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let prf = HmacSha256::new_from_slice(password.as_bytes()).expect("HMAC takes any key size");
+    let mut u = prf
+        .clone()
+        .chain_update(salt)
+        .chain_update(1u32.to_be_bytes())
+        .finalize()
+        .into_bytes();
+    let mut block: [u8; 32] = u.into();
+    for _ in 1..iterations {
+        u = prf.clone().chain_update(u).finalize().into_bytes();
+        for (b, x) in block.iter_mut().zip(u.iter()) {
+            *b ^= x;
+        }
+    }
+    block
+}
+
+/// Compares every byte so the time taken doesn't tell how much matched
+fn constant_time_eq(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pbkdf2, PasswordHash};
+
+    #[test]
+    fn verify() {
+        let hash = PasswordHash::new("secret");
+        assert!(hash.verify("secret"));
+        assert!(!hash.verify("Secret"));
+        assert!(!hash.verify(""));
+        let other = PasswordHash::with_iterations("secret", 10);
+        assert_ne!(hash.hash, other.hash);
+        assert!(other.verify("secret"));
+        assert!(!other.verify("secrets"));
+    }
+
+    #[test]
+    fn test_vectors() {
+        assert_eq!(
+            hex::encode(pbkdf2("password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex::encode(pbkdf2("password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+}
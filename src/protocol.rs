@@ -0,0 +1,984 @@
+//! What clients and `WsSession` say to each other. A client sends a
+//! `Request` as a JSON object with the protocol version,
+//! `{"v": 1, "cmd": "join", "room": "room1"}`, and gets `Response`s back in
+//! the same form. Anything that goes wrong comes back as an `Error`,
+//! `{"v": 1, "cmd": "error", "code": "missing_parameter", ...}`.
+//!
+//! A request may carry an `id`, a number or a string, which comes back as
+//! `id` in its reply or error so that clients can send several requests
+//! without waiting. `Event`s the server pushes never have an `id`.
+//!
+//! The older slash commands such as `/join room1` are read by
+//! `Request::parse_command` into the same requests, and any other text is
+//! chat.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+
+use crate::ai::Level;
+use crate::clock::{ClockState, TimeControl};
+use crate::game::{GameResult, Player};
+use crate::reversi::{History, LegalMove};
+use crate::server::{
+    ChatMessage, DrawAction, Member, PrivateChat, Rename, RoomInfo, RoomOptions, UndoAction,
+    Visibility,
+};
+
+/// Version of the JSON protocol, requests must carry it as `v`
+pub const VERSION: u32 = 1;
+
+/// Every `cmd` of `Request` with the fields it can't do without
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("room", &[]),
+    ("list", &[]),
+    ("join", &["room"]),
+    ("name", &["name"]),
+    ("members", &[]),
+    ("start", &[]),
+    ("get_player", &["seat"]),
+    ("sit", &["seat"]),
+    ("bot", &["seat"]),
+    ("put_disc", &["x", "y"]),
+    ("hints", &[]),
+    ("history", &[]),
+    ("chat_history", &[]),
+    ("undo", &["action"]),
+    ("resign", &[]),
+    ("draw", &["action"]),
+    ("invite", &[]),
+    ("revoke_invite", &["token"]),
+    ("kick", &["name"]),
+    ("ban", &["name"]),
+    ("unban", &["name"]),
+    ("moderator", &["name"]),
+    ("say", &["text"]),
+    ("private_message", &["to", "text"]),
+];
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    /// the room of the session
+    Room,
+    List {
+        /// only rooms with an empty seat
+        #[serde(default)]
+        open_seats: bool,
+        /// only rooms where a game is running
+        #[serde(default)]
+        in_progress: bool,
+        #[serde(default)]
+        offset: usize,
+        limit: Option<usize>,
+    },
+    Join {
+        room: String,
+        /// settings if the room is created, and its password
+        #[serde(flatten)]
+        options: RoomOptions,
+        /// wait in line if the room is full
+        #[serde(default)]
+        queue: bool,
+        invite: Option<String>,
+    },
+    Name {
+        name: String,
+    },
+    Members,
+    Start {
+        /// board size, even from 4 to 10 for Reversi
+        size: Option<usize>,
+        time: Option<TimeSetting>,
+    },
+    GetPlayer {
+        seat: Player,
+    },
+    /// take a seat at the table
+    Sit {
+        seat: Player,
+    },
+    /// seat a computer player
+    Bot {
+        seat: Player,
+        #[serde(default = "normal")]
+        level: Level,
+    },
+    PutDisc {
+        x: usize,
+        y: usize,
+    },
+    Hints,
+    /// record of the current game
+    History,
+    /// chat messages of the room before the message `before`, or the
+    /// latest ones
+    ChatHistory {
+        before: Option<u64>,
+    },
+    Undo {
+        action: UndoAction,
+    },
+    Resign,
+    Draw {
+        action: DrawAction,
+    },
+    Invite {
+        #[serde(default = "yes")]
+        single_use: bool,
+        /// seconds the token is valid, at most 30 days
+        ttl: Option<u64>,
+    },
+    RevokeInvite {
+        token: String,
+    },
+    Kick {
+        name: String,
+    },
+    Ban {
+        name: String,
+    },
+    Unban {
+        name: String,
+    },
+    Moderator {
+        name: String,
+        /// false takes the moderator rights back
+        #[serde(default = "yes")]
+        moderator: bool,
+    },
+    /// chat to the room
+    Say {
+        text: String,
+    },
+    /// message to one user, `to` is its public id or name
+    PrivateMessage {
+        to: String,
+        text: String,
+    },
+}
+
+fn normal() -> Level {
+    Level::Normal
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// Time control of a `start` request, in seconds
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TimeSetting {
+    Fischer {
+        base: u64,
+        increment: u64,
+    },
+    ByoYomi {
+        base: u64,
+        period: u64,
+        periods: u32,
+    },
+}
+
+impl TimeSetting {
+    /// `None` for a byo-yomi without periods or times over the limits of
+    /// `TimeControl::valid`
+    pub fn control(self) -> Option<TimeControl> {
+        let control = match self {
+            TimeSetting::Fischer { base, increment } => TimeControl::Fischer {
+                base: Duration::from_secs(base),
+                increment: Duration::from_secs(increment),
+            },
+            TimeSetting::ByoYomi {
+                base,
+                period,
+                periods,
+            } => TimeControl::ByoYomi {
+                base: Duration::from_secs(base),
+                period: Duration::from_secs(period),
+                periods,
+            },
+        };
+        Some(control).filter(TimeControl::valid)
+    }
+}
+
+/// Id the client gave a request
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(u64),
+    String(String),
+}
+
+/// What the header of a JSON request has to carry
+#[derive(Deserialize)]
+struct Header {
+    v: Option<u32>,
+    cmd: Option<String>,
+    id: Option<RequestId>,
+}
+
+impl Request {
+    /// Reads a message from a client: a JSON request if it starts with `{`,
+    /// a slash command if it starts with `/`, otherwise chat. Returns the
+    /// id of the request too, also when it can't be read.
+    pub fn parse(text: &str) -> (Option<RequestId>, Result<Request, Error>) {
+        let text = text.trim();
+        if text.starts_with('{') {
+            Request::parse_json(text)
+        } else if text.starts_with('/') {
+            (None, Request::parse_command(text))
+        } else {
+            let say = Request::Say {
+                text: text.to_owned(),
+            };
+            (None, Ok(say))
+        }
+    }
+
+    pub fn parse_json(text: &str) -> (Option<RequestId>, Result<Request, Error>) {
+        let value: Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(e) => return (None, Err(Error::new(ErrorCode::BadRequest, e))),
+        };
+        match Header::deserialize(&value) {
+            Ok(header) => (header.id.clone(), Request::from_json(&value, header)),
+            Err(e) => (None, Err(Error::new(ErrorCode::BadRequest, e))),
+        }
+    }
+
+    fn from_json(value: &Value, header: Header) -> Result<Request, Error> {
+        match header.v {
+            Some(VERSION) => (),
+            Some(v) => {
+                return Err(Error::new(
+                    ErrorCode::UnsupportedVersion,
+                    format!("version {} is not supported, use {}", v, VERSION),
+                ))
+            }
+            None => return Err(Error::new(ErrorCode::BadRequest, "v is required")),
+        }
+        let cmd = match header.cmd {
+            Some(cmd) => cmd,
+            None => return Err(Error::new(ErrorCode::BadRequest, "cmd is required")),
+        };
+        let required = match COMMANDS.iter().find(|(name, _)| *name == cmd) {
+            Some((_, required)) => *required,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::UnknownCommand,
+                    format!("unknown command: {}", cmd),
+                ))
+            }
+        };
+        let missing = required
+            .iter()
+            .find(|field| value.get(**field).is_none_or(Value::is_null));
+        if let Some(field) = missing {
+            return Err(Error::new(
+                ErrorCode::MissingParameter,
+                format!("{} is required", field),
+            ));
+        }
+        Request::deserialize(value).map_err(|e| Error::new(ErrorCode::InvalidParameter, e))
+    }
+
+    /// Reads a legacy slash command such as `/join room1 size=10`
+    pub fn parse_command(text: &str) -> Result<Request, Error> {
+        let v: Vec<&str> = text.split_whitespace().collect();
+        let args = &v[1..];
+        let request = match v[0] {
+            "/room" => Request::Room,
+            "/list" => {
+                let (mut open_seats, mut in_progress, mut offset, mut limit) =
+                    (false, false, 0, None);
+                for arg in args {
+                    match (*arg, arg.split_once('=')) {
+                        ("open", _) => open_seats = true,
+                        ("playing", _) => in_progress = true,
+                        (_, Some(("offset", value))) => offset = number(value)?,
+                        (_, Some(("limit", value))) => limit = Some(number(value)?),
+                        _ => return Err(unknown_option(arg)),
+                    }
+                }
+                Request::List {
+                    open_seats,
+                    in_progress,
+                    offset,
+                    limit,
+                }
+            }
+            "/join" => {
+                let room = required(args.first(), "room name is required")?;
+                let mut options = RoomOptions::default();
+                let mut queue = false;
+                let mut invite = None;
+                for arg in &args[1..] {
+                    if *arg == "queue" {
+                        queue = true;
+                        continue;
+                    }
+                    match arg.split_once('=') {
+                        Some(("game", "none")) => options.game = None,
+                        Some(("game", value)) => options.game = Some(value.to_owned()),
+                        Some(("size", value)) => options.size = Some(number(value)?),
+                        Some(("capacity", value)) => options.capacity = Some(number(value)?),
+                        Some(("visibility", value)) => {
+                            options.visibility =
+                                Visibility::parse(value).ok_or_else(|| invalid(arg))?
+                        }
+                        Some(("password", value)) => options.password = Some(value.to_owned()),
+                        // an invite token
+                        None => invite = Some(arg.to_string()),
+                        _ => return Err(unknown_option(arg)),
+                    }
+                }
+                Request::Join {
+                    room,
+                    options,
+                    queue,
+                    invite,
+                }
+            }
+            "/name" => match args {
+                [name] => Request::Name {
+                    name: name.to_string(),
+                },
+                _ => return Err(missing("name is required")),
+            },
+            "/members" => Request::Members,
+            "/start" => {
+                let mut size = None;
+                let mut time = None;
+                for arg in args {
+                    match arg.split_once('=') {
+                        Some(("size", value)) => size = Some(number(value)?),
+                        Some((key @ ("time" | "byoyomi"), value)) => {
+                            time = Some(TimeSetting::parse(key, value).ok_or_else(|| invalid(arg))?)
+                        }
+                        _ => return Err(unknown_option(arg)),
+                    }
+                }
+                Request::Start { size, time }
+            }
+            cmd @ ("/player1" | "/player2") => {
+                let seat = if cmd == "/player1" {
+                    Player::One
+                } else {
+                    Player::Two
+                };
+                match args {
+                    ["get"] => Request::GetPlayer { seat },
+                    ["regist"] => Request::Sit { seat },
+                    ["bot"] => Request::Bot {
+                        seat,
+                        level: Level::Normal,
+                    },
+                    ["bot", level] => Request::Bot {
+                        seat,
+                        level: Level::parse(level).ok_or_else(|| {
+                            Error::new(
+                                ErrorCode::InvalidParameter,
+                                "level must be easy, normal or hard",
+                            )
+                        })?,
+                    },
+                    _ => return Err(missing("get, regist or bot is required")),
+                }
+            }
+            "/put_disc" => match args {
+                [x, y] => Request::PutDisc {
+                    x: number(x)?,
+                    y: number(y)?,
+                },
+                _ => return Err(missing("x and y are required")),
+            },
+            "/hints" => Request::Hints,
+            "/history" => Request::History,
+            // `/chatlog` for the latest messages, `/chatlog 42` for the ones
+            // before the message 42
+            "/chatlog" => match args {
+                [] => Request::ChatHistory { before: None },
+                [before] => Request::ChatHistory {
+                    before: Some(number(before)?),
+                },
+                _ => return Err(invalid(&args.join(" "))),
+            },
+            "/undo" => Request::Undo {
+                action: match args.first() {
+                    None => UndoAction::Request,
+                    Some(&"accept") => UndoAction::Accept,
+                    Some(&"reject") => UndoAction::Reject,
+                    Some(arg) => return Err(invalid(arg)),
+                },
+            },
+            "/resign" => Request::Resign,
+            "/draw" => Request::Draw {
+                action: match args.first() {
+                    Some(&"offer") => DrawAction::Offer,
+                    Some(&"accept") => DrawAction::Accept,
+                    _ => return Err(missing("offer or accept is required")),
+                },
+            },
+            "/invite" if args.first() == Some(&"revoke") => Request::RevokeInvite {
+                token: required(args.get(1), "token is required")?,
+            },
+            "/invite" => {
+                let mut single_use = true;
+                let mut ttl = None;
+                for arg in args {
+                    match (*arg, arg.split_once('=')) {
+                        ("single", _) => single_use = true,
+                        ("multi", _) => single_use = false,
+                        (_, Some(("ttl", value))) => ttl = Some(number(value)?),
+                        _ => return Err(unknown_option(arg)),
+                    }
+                }
+                Request::Invite { single_use, ttl }
+            }
+            cmd @ ("/kick" | "/ban" | "/unban") => {
+                let name = match args {
+                    [name] => name.to_string(),
+                    _ => return Err(missing("name is required")),
+                };
+                match cmd {
+                    "/kick" => Request::Kick { name },
+                    "/ban" => Request::Ban { name },
+                    _ => Request::Unban { name },
+                }
+            }
+            // `/msg <user> <text>`, the text keeps its spaces
+            "/msg" => {
+                let rest = text["/msg".len()..].trim_start();
+                match rest.split_once(char::is_whitespace) {
+                    Some((to, text)) if !text.trim().is_empty() => Request::PrivateMessage {
+                        to: to.to_owned(),
+                        text: text.trim_start().to_owned(),
+                    },
+                    _ => return Err(missing("user and text are required")),
+                }
+            }
+            // `/mod <name>` or `/mod remove <name>`
+            "/mod" => match args {
+                [name] => Request::Moderator {
+                    name: name.to_string(),
+                    moderator: true,
+                },
+                ["remove", name] => Request::Moderator {
+                    name: name.to_string(),
+                    moderator: false,
+                },
+                _ => return Err(missing("name is required")),
+            },
+            _ => {
+                return Err(Error::new(
+                    ErrorCode::UnknownCommand,
+                    format!("unknown command: {:?}", text),
+                ))
+            }
+        };
+        Ok(request)
+    }
+
+    /// the `cmd` of the request, named in its errors
+    pub fn name(&self) -> &'static str {
+        match self {
+            Request::Room => "room",
+            Request::List { .. } => "list",
+            Request::Join { .. } => "join",
+            Request::Name { .. } => "name",
+            Request::Members => "members",
+            Request::Start { .. } => "start",
+            Request::GetPlayer { .. } => "get_player",
+            Request::Sit { .. } => "sit",
+            Request::Bot { .. } => "bot",
+            Request::PutDisc { .. } => "put_disc",
+            Request::Hints => "hints",
+            Request::History => "history",
+            Request::ChatHistory { .. } => "chat_history",
+            Request::Undo { .. } => "undo",
+            Request::Resign => "resign",
+            Request::Draw { .. } => "draw",
+            Request::Invite { .. } => "invite",
+            Request::RevokeInvite { .. } => "revoke_invite",
+            Request::Kick { .. } => "kick",
+            Request::Ban { .. } => "ban",
+            Request::Unban { .. } => "unban",
+            Request::Moderator { .. } => "moderator",
+            Request::Say { .. } => "say",
+            Request::PrivateMessage { .. } => "private_message",
+        }
+    }
+}
+
+impl TimeSetting {
+    /// `time=300+5` or `byoyomi=600+30x3` of `/start`
+    fn parse(key: &str, value: &str) -> Option<TimeSetting> {
+        let secs = |d: Duration| d.as_secs();
+        match TimeControl::parse(key, value)? {
+            TimeControl::Fischer { base, increment } => Some(TimeSetting::Fischer {
+                base: secs(base),
+                increment: secs(increment),
+            }),
+            TimeControl::ByoYomi {
+                base,
+                period,
+                periods,
+            } => Some(TimeSetting::ByoYomi {
+                base: secs(base),
+                period: secs(period),
+                periods,
+            }),
+        }
+    }
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> Result<T, Error> {
+    s.parse().map_err(|_| invalid(s))
+}
+
+fn required(arg: Option<&&str>, message: &str) -> Result<String, Error> {
+    arg.map(|s| s.to_string()).ok_or_else(|| missing(message))
+}
+
+fn invalid(arg: &str) -> Error {
+    Error::new(
+        ErrorCode::InvalidParameter,
+        format!("invalid parameter: {:?}", arg),
+    )
+}
+
+fn missing(message: &str) -> Error {
+    Error::new(ErrorCode::MissingParameter, message)
+}
+
+fn unknown_option(arg: &str) -> Error {
+    Error::new(
+        ErrorCode::InvalidParameter,
+        format!("unknown option: {:?}", arg),
+    )
+}
+
+/// Direct replies to requests. What the server pushes unasked is an `Event`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Response {
+    Room {
+        data: String,
+    },
+    List {
+        data: Vec<RoomInfo>,
+        /// rooms matching the filters, on all pages
+        total: usize,
+        offset: usize,
+    },
+    /// the session is in the room `data` now
+    Join {
+        data: String,
+    },
+    /// the room is full, `data` is the place in line
+    Queued {
+        data: usize,
+    },
+    Members {
+        data: Vec<Member>,
+    },
+    /// name of the player in `seat`, empty if nobody sits there
+    Player {
+        seat: usize,
+        data: String,
+    },
+    /// the session took the seat `data`
+    RegisteredYou {
+        data: usize,
+    },
+    Hints {
+        data: Vec<LegalMove>,
+    },
+    History {
+        data: Option<History>,
+    },
+    ChatHistory {
+        data: Vec<ChatMessage>,
+    },
+    /// an invite token
+    Invite {
+        data: String,
+    },
+    /// the request `request` was carried out, `data` tells how if there is
+    /// more than one way
+    Done {
+        request: &'static str,
+        data: Option<String>,
+    },
+}
+
+impl Response {
+    /// `id` is the id of the request this replies to
+    pub fn to_json(&self, id: Option<&RequestId>) -> String {
+        envelope(self, id)
+    }
+}
+
+/// What the server pushes to the members of a room or to one session
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Event {
+    /// everyone in `room`, sent to a session entering it
+    Members {
+        room: String,
+        data: Vec<Member>,
+    },
+    /// the latest chat of `room`, sent to a session entering it
+    ChatHistory {
+        room: String,
+        data: Vec<ChatMessage>,
+    },
+    MemberJoined {
+        room: String,
+        data: Member,
+    },
+    MemberLeft {
+        room: String,
+        data: Member,
+    },
+    MemberRenamed {
+        room: String,
+        data: Rename,
+    },
+    Chat {
+        room: String,
+        data: ChatMessage,
+    },
+    PrivateMessage {
+        data: PrivateChat,
+    },
+    /// the session was sent from the room `data` back to Main
+    Kicked {
+        data: String,
+    },
+    /// kicked and kept out of the room `data`
+    Banned {
+        data: String,
+    },
+    /// the room `data` is gone, sent to the sessions that listed the rooms
+    RoomClosed {
+        data: String,
+    },
+    /// `data` took seat 1, the name of a user or a bot
+    RegisteredPlayer1 {
+        data: String,
+    },
+    RegisteredPlayer2 {
+        data: String,
+    },
+    /// the game in the room after every change
+    UpdateState {
+        /// game type
+        game: &'static str,
+        clock: Option<ClockState>,
+        /// `Game::to_json`, at least `state` and `data`
+        #[serde(flatten)]
+        state: Map<String, Value>,
+    },
+    /// sent once, right after the game ended
+    GameOver {
+        data: GameResult,
+    },
+    /// the player `data` asks to take back the last move
+    UndoRequested {
+        data: String,
+    },
+    UndoAccepted,
+    UndoRejected,
+    /// the player `data` offers a draw
+    DrawOffered {
+        data: String,
+    },
+    ModeratorAdded {
+        data: String,
+    },
+    ModeratorRemoved {
+        data: String,
+    },
+}
+
+impl Event {
+    pub fn to_json(&self) -> String {
+        envelope(self, None)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// not JSON or without `v` or `cmd`
+    BadRequest,
+    UnsupportedVersion,
+    UnknownCommand,
+    MissingParameter,
+    InvalidParameter,
+    /// the request was understood but can't be carried out now
+    Rejected,
+    /// `put_disc` can't put a disc there, `data` says why
+    IllegalMove,
+    /// the server didn't answer
+    Internal,
+}
+
+/// `{"cmd": "error", ...}`, the reply to any request that failed
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "cmd", rename = "error")]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    /// `cmd` of the failed request, if it could be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl ToString) -> Self {
+        Error {
+            code,
+            message: message.to_string(),
+            request: None,
+            data: None,
+        }
+    }
+
+    /// the server turned down `request`, named by `Request::name`
+    pub fn rejected(request: &'static str, message: impl ToString) -> Self {
+        Error::new(ErrorCode::Rejected, message).of(request)
+    }
+
+    pub fn of(mut self, request: &'static str) -> Self {
+        self.request = Some(request);
+        self
+    }
+
+    pub fn to_json(&self, id: Option<&RequestId>) -> String {
+        envelope(self, id)
+    }
+}
+
+fn envelope<T: Serialize>(msg: &T, id: Option<&RequestId>) -> String {
+    let mut value = serde_json::to_value(msg).expect("replies serialize to JSON objects");
+    value["v"] = VERSION.into();
+    if let Some(id) = id {
+        value["id"] = json!(id);
+    }
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, ErrorCode, Event, Request, RequestId, Response, TimeSetting, COMMANDS};
+    use crate::game::Player;
+    use crate::server::{RoomOptions, UndoAction};
+    use serde_json::{json, Map};
+
+    fn parse(text: &str) -> Result<Request, Error> {
+        Request::parse(text).1
+    }
+
+    #[test]
+    fn json_requests() {
+        assert_eq!(
+            parse(r#"{"v": 1, "cmd": "join", "room": "a", "size": 10}"#),
+            Ok(Request::Join {
+                room: "a".to_owned(),
+                options: RoomOptions {
+                    size: Some(10),
+                    ..RoomOptions::default()
+                },
+                queue: false,
+                invite: None,
+            })
+        );
+        assert_eq!(
+            parse(r#"{"v": 1, "cmd": "join", "room": "a", "game": null}"#)
+                .map(|r| matches!(r, Request::Join { options, .. } if options.game.is_none())),
+            Ok(true)
+        );
+        assert_eq!(
+            parse(r#"{"v": 1, "cmd": "sit", "seat": 2}"#),
+            Ok(Request::Sit { seat: Player::Two })
+        );
+        let code = |text| parse(text).unwrap_err().code;
+        assert_eq!(
+            code(r#"{"v": 2, "cmd": "room"}"#),
+            ErrorCode::UnsupportedVersion
+        );
+        assert_eq!(code(r#"{"cmd": "room"}"#), ErrorCode::BadRequest);
+        assert_eq!(code(r#"{"v": 1, "cmd": "fly"}"#), ErrorCode::UnknownCommand);
+        assert_eq!(
+            code(r#"{"v": 1, "cmd": "put_disc", "x": 1}"#),
+            ErrorCode::MissingParameter
+        );
+        assert_eq!(
+            code(r#"{"v": 1, "cmd": "sit", "seat": 3}"#),
+            ErrorCode::InvalidParameter
+        );
+        assert_eq!(code("{v: 1}"), ErrorCode::BadRequest);
+    }
+
+    #[test]
+    fn commands() {
+        let sample = |field: &str| match field {
+            "seat" => json!(1),
+            "x" | "y" => json!(2),
+            "action" => json!("accept"),
+            _ => json!("bob"),
+        };
+        for (cmd, required) in COMMANDS {
+            let mut request = json!({"v": 1, "cmd": cmd});
+            if let Some(field) = required.first() {
+                let error = parse(&request.to_string()).unwrap_err();
+                assert_eq!(error.code, ErrorCode::MissingParameter);
+                assert_eq!(error.message, format!("{} is required", field));
+            }
+            for field in *required {
+                request[field] = sample(field);
+            }
+            assert_eq!(parse(&request.to_string()).unwrap().name(), *cmd);
+        }
+    }
+
+    #[test]
+    fn slash_commands() {
+        assert_eq!(
+            parse("/start size=10 byoyomi=600+30x3"),
+            Ok(Request::Start {
+                size: Some(10),
+                time: Some(TimeSetting::ByoYomi {
+                    base: 600,
+                    period: 30,
+                    periods: 3,
+                }),
+            })
+        );
+        assert_eq!(
+            parse("/undo"),
+            Ok(Request::Undo {
+                action: UndoAction::Request
+            })
+        );
+        assert_eq!(
+            parse("hello /join"),
+            Ok(Request::Say {
+                text: "hello /join".to_owned()
+            })
+        );
+        let code = |text| parse(text).unwrap_err().code;
+        assert_eq!(parse("/history"), Ok(Request::History));
+        assert_eq!(parse("/chatlog"), Ok(Request::ChatHistory { before: None }));
+        assert_eq!(
+            parse("/chatlog 42"),
+            Ok(Request::ChatHistory { before: Some(42) })
+        );
+        assert_eq!(code("/chatlog latest"), ErrorCode::InvalidParameter);
+        assert_eq!(
+            parse("/msg bob  see  you"),
+            Ok(Request::PrivateMessage {
+                to: "bob".to_owned(),
+                text: "see  you".to_owned()
+            })
+        );
+        assert_eq!(code("/msg bob"), ErrorCode::MissingParameter);
+        assert_eq!(code("/join"), ErrorCode::MissingParameter);
+        assert_eq!(code("/put_disc 1 x"), ErrorCode::InvalidParameter);
+        let error = parse("/join room1 size=big").unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidParameter);
+        assert_eq!(error.message, r#"invalid parameter: "big""#);
+        assert_eq!(
+            code("/start byoyomi=0+18446744073709551615x2"),
+            ErrorCode::InvalidParameter
+        );
+        assert_eq!(code("/fly"), ErrorCode::UnknownCommand);
+    }
+
+    #[test]
+    fn time_settings() {
+        let fischer = |base, increment| TimeSetting::Fischer { base, increment };
+        assert!(fischer(300, 5).control().is_some());
+        assert_eq!(fischer(u64::MAX, 5).control(), None);
+        assert_eq!(fischer(300, u64::MAX).control(), None);
+        let byo_yomi = |period, periods| TimeSetting::ByoYomi {
+            base: 0,
+            period,
+            periods,
+        };
+        assert!(byo_yomi(30, 3).control().is_some());
+        assert_eq!(byo_yomi(30, 0).control(), None);
+        assert_eq!(byo_yomi(u64::MAX, 2).control(), None);
+        assert_eq!(byo_yomi(30, u32::MAX).control(), None);
+    }
+
+    #[test]
+    fn replies() {
+        let json: serde_json::Value =
+            serde_json::from_str(&Response::Queued { data: 2 }.to_json(None)).unwrap();
+        assert_eq!(json["cmd"], "queued");
+        assert_eq!(json["v"], 1);
+        assert!(json.get("id").is_none());
+        let error = Error::rejected(Request::Resign.name(), "no game is running");
+        let id = RequestId::String("r1".to_owned());
+        let json: serde_json::Value = serde_json::from_str(&error.to_json(Some(&id))).unwrap();
+        assert_eq!(json["cmd"], "error");
+        assert_eq!(json["code"], "rejected");
+        assert_eq!(json["request"], "resign");
+        assert_eq!(json["id"], "r1");
+        assert!(json.get("data").is_none());
+    }
+
+    #[test]
+    fn events() {
+        let parse =
+            |event: Event| -> serde_json::Value { serde_json::from_str(&event.to_json()).unwrap() };
+        let json = parse(Event::UndoAccepted);
+        assert_eq!(json, json!({"v": 1, "cmd": "undo_accepted"}));
+        let json = parse(Event::RegisteredPlayer2 {
+            data: "bob".to_owned(),
+        });
+        assert_eq!(json["cmd"], "registered_player2");
+        assert_eq!(json["data"], "bob");
+
+        let mut state = Map::new();
+        state.insert("state".to_owned(), json!("end"));
+        state.insert("data".to_owned(), json!([[0, 1]]));
+        let json = parse(Event::UpdateState {
+            game: "reversi",
+            clock: None,
+            state,
+        });
+        assert_eq!(
+            json,
+            json!({
+                "v": 1,
+                "cmd": "update_state",
+                "game": "reversi",
+                "clock": null,
+                "state": "end",
+                "data": [[0, 1]],
+            })
+        );
+    }
+
+    #[test]
+    fn request_ids() {
+        let (id, request) = Request::parse(r#"{"v": 1, "cmd": "members", "id": 7}"#);
+        assert_eq!(id, Some(RequestId::Number(7)));
+        assert_eq!(request, Ok(Request::Members));
+        // errors after the header is read keep the id
+        let (id, request) = Request::parse(r#"{"v": 1, "cmd": "fly", "id": "a"}"#);
+        assert_eq!(id, Some(RequestId::String("a".to_owned())));
+        assert!(request.is_err());
+        assert_eq!(Request::parse(r#"{"v": 1, "id": [1]}"#).0, None);
+        assert_eq!(Request::parse("/members").0, None);
+    }
+}
@@ -1,11 +1,133 @@
 use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::any::Any;
 use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+pub use crate::game::{EndReason, GameResult, SetDiscError, States, Winner};
+use crate::game::{Game, Player};
+
+pub mod ggf;
+pub mod wthor;
+
+/// Error while reading a game record
+#[derive(Debug, PartialEq)]
+pub enum RecordError {
+    /// The record is malformed
+    Format(String),
+    /// The record is valid but describes something the engine can't play,
+    /// such as another board size or starting position
+    Unsupported(String),
+    /// The `index`th move of the record is not legal
+    IllegalMove { index: usize, error: SetDiscError },
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::Format(msg) => write!(f, "invalid record: {}", msg),
+            RecordError::Unsupported(msg) => write!(f, "unsupported record: {}", msg),
+            RecordError::IllegalMove { index, error } => {
+                write!(f, "illegal move #{}: {}", index + 1, error)
+            }
+        }
+    }
+}
+
+/// A square the player to move can put a disc on
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct LegalMove {
+    pub x: usize,
+    pub y: usize,
+    /// squares flipped by this move
+    pub flips: Vec<(usize, usize)>,
+}
+
+/// A recorded move
 #[derive(Serialize, Clone, Debug, PartialEq)]
-pub enum States {
-    End,
-    TurnPlayer1,
-    TurnPlayer2,
+pub struct Move {
+    /// 1 or 2
+    pub player: u8,
+    /// id of the player who moved
+    pub player_id: usize,
+    /// (x, y) of the disc, `None` for a pass
+    pub square: Option<(usize, usize)>,
+    /// milliseconds since the UNIX epoch
+    pub time: u64,
+}
+
+impl Move {
+    fn new(player: u8, player_id: usize, square: Option<(usize, usize)>) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        Move {
+            player,
+            player_id,
+            square,
+            time,
+        }
+    }
+
+    /// Othello coordinate such as "f5" on a board of `size`, "pass" for a pass
+    pub fn notation(&self, size: usize) -> String {
+        match self.square.map(|s| mirror(size, s)) {
+            Some((x, y)) => format!("{}{}", (b'a' + x as u8) as char, y + 1),
+            None => "pass".to_owned(),
+        }
+    }
+}
+
+/// Moves of the current game
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct History {
+    /// board size of the game
+    pub size: usize,
+    pub moves: Vec<Move>,
+    /// moves without passes, e.g. "f5d6c3"
+    pub transcript: String,
+}
+
+/// Parses an Othello coordinate such as "f5" or "F5" into (x, y)
+pub(crate) fn parse_square(s: &str) -> Option<(usize, usize)> {
+    let mut chars = s.chars();
+    let x = match chars.next()?.to_ascii_lowercase() {
+        c @ 'a'..='z' => c as usize - 'a' as usize,
+        _ => return None,
+    };
+    let y = match chars.as_str().parse::<usize>() {
+        Ok(y) if (1..=MAX_SIZE).contains(&y) => y - 1,
+        _ => return None,
+    };
+    Some((x, y))
+}
+
+/// Mirrors a square left to right. Player1 starts on d4 and e5 here while
+/// black, who moves first in Othello, starts on d5 and e4, so notation and
+/// records show every column mirrored.
+pub(crate) fn mirror(size: usize, (x, y): (usize, usize)) -> (usize, usize) {
+    (size - 1 - x, y)
+}
+
+/// Splits milliseconds since the UNIX epoch into
+/// (year, month, day, hour, minute, second) in UTC
+pub(crate) fn utc(millis: u64) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = millis / 1000;
+    let days = (secs / 86400) as i64;
+    let rem = (secs % 86400) as u32;
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day, rem / 3600, rem / 60 % 60, rem % 60)
 }
 
 #[derive(PartialEq, Clone)]
@@ -21,12 +143,227 @@ impl Disc {
     }
 }
 
+/// Smallest board size
+pub const MIN_SIZE: usize = 4;
+/// Largest board size. Boards are u128 bitboards, so a side of 12 or more
+/// does not fit and is rejected rather than supporting any even size.
+pub const MAX_SIZE: usize = 10;
+pub const DEFAULT_SIZE: usize = 8;
+
+/// Board size and the masks that shift-based move generation needs.
+/// Bit `y * size + x` of a bitboard stands for the square (x, y).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Geometry {
+    pub size: usize,
+    /// every square of the board
+    all: u128,
+    /// squares that can be reached by shifting east, without column a
+    not_west: u128,
+    /// squares that can be reached by shifting west, without the last column
+    not_east: u128,
+}
+
+impl Geometry {
+    /// `size` must be an even number from `MIN_SIZE` to `MAX_SIZE`
+    pub fn new(size: usize) -> Self {
+        assert!(Self::valid(size), "invalid board size {}", size);
+        let all = (1u128 << (size * size)) - 1;
+        let row = (1u128 << size) - 1;
+        let west = all / row;
+        Geometry {
+            size,
+            all,
+            not_west: all & !west,
+            not_east: all & !(west << (size - 1)),
+        }
+    }
+
+    pub fn valid(size: usize) -> bool {
+        (MIN_SIZE..=MAX_SIZE).contains(&size) && size.is_multiple_of(2)
+    }
+
+    pub fn square(&self, x: usize, y: usize) -> usize {
+        y * self.size + x
+    }
+
+    /// Shifts a bitboard one square towards the direction `dir` (0 to 7)
+    fn shift(&self, b: u128, dir: usize) -> u128 {
+        let n = self.size;
+        match dir {
+            0 => (b << 1) & self.not_west,
+            1 => (b >> 1) & self.not_east,
+            2 => (b << n) & self.all,
+            3 => b >> n,
+            4 => (b << (n + 1)) & self.not_west,
+            5 => (b >> (n + 1)) & self.not_east,
+            6 => (b >> (n - 1)) & self.not_west,
+            _ => (b << (n - 1)) & self.not_east,
+        }
+    }
+
+    /// Bitboard of every legal move of `own` against `opp`
+    pub fn moves(&self, own: u128, opp: u128) -> u128 {
+        let empty = self.all & !(own | opp);
+        let mut moves = 0;
+        for dir in 0..8 {
+            let mut line = self.shift(own, dir) & opp;
+            for _ in 0..self.size - 3 {
+                line |= self.shift(line, dir) & opp;
+            }
+            moves |= self.shift(line, dir) & empty;
+        }
+        moves
+    }
+
+    /// Bitboard of the discs flipped when `own` puts a disc on `square`
+    pub fn flips(&self, own: u128, opp: u128, square: usize) -> u128 {
+        let mut flips = 0;
+        for dir in 0..8 {
+            let mut line = 0;
+            let mut b = self.shift(1 << square, dir);
+            while b & opp != 0 {
+                line |= b;
+                b = self.shift(b, dir);
+            }
+            if b & own != 0 {
+                flips |= line;
+            }
+        }
+        flips
+    }
+
+    /// Converts a bitboard into (x, y) squares
+    pub fn squares(&self, mut bits: u128) -> Vec<(usize, usize)> {
+        let mut ret = Vec::new();
+        while bits != 0 {
+            let square = bits.trailing_zeros() as usize;
+            ret.push((square % self.size, square / self.size));
+            bits &= bits - 1;
+        }
+        ret
+    }
+}
+
+/// Discs on the board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Board {
+    pub geometry: Geometry,
+    /// player2's discs
+    pub black: u128,
+    /// player1's discs
+    pub white: u128,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::empty(DEFAULT_SIZE)
+    }
+}
+
+impl Board {
+    pub fn empty(size: usize) -> Self {
+        Self {
+            geometry: Geometry::new(size),
+            black: 0,
+            white: 0,
+        }
+    }
+
+    /// The four discs in the center
+    pub fn start(size: usize) -> Self {
+        let mut board = Self::empty(size);
+        let (c, g) = (size / 2, board.geometry);
+        board.black = 1 << g.square(c, c - 1) | 1 << g.square(c - 1, c);
+        board.white = 1 << g.square(c - 1, c - 1) | 1 << g.square(c, c);
+        board
+    }
+
+    pub fn size(&self) -> usize {
+        self.geometry.size
+    }
+
+    /// Builds a board from the `get_discs` representation,
+    /// the number of rows is the size of the board
+    pub fn from_discs(discs: &[Vec<u8>]) -> Self {
+        let mut board = Board::empty(discs.len());
+        let g = board.geometry;
+        for (y, row) in discs.iter().enumerate() {
+            for (x, d) in row.iter().enumerate().take(g.size) {
+                if *d == Disc::Black.u8() {
+                    board.black |= 1 << g.square(x, y);
+                } else if *d == Disc::White.u8() {
+                    board.white |= 1 << g.square(x, y);
+                }
+            }
+        }
+        board
+    }
+
+    pub fn to_discs(&self) -> Vec<Vec<u8>> {
+        let g = self.geometry;
+        let mut discs = vec![vec![Disc::None.u8(); g.size]; g.size];
+        for (x, y) in g.squares(self.black) {
+            discs[y][x] = Disc::Black.u8();
+        }
+        for (x, y) in g.squares(self.white) {
+            discs[y][x] = Disc::White.u8();
+        }
+        discs
+    }
+
+    /// (own, opponent) bitboards seen from `disc`
+    fn sides(&self, disc: &Disc) -> (u128, u128) {
+        match disc {
+            Disc::Black => (self.black, self.white),
+            _ => (self.white, self.black),
+        }
+    }
+
+    fn put(&mut self, disc: &Disc, square: usize, flips: u128) {
+        let placed = flips | 1 << square;
+        match disc {
+            Disc::Black => {
+                self.black |= placed;
+                self.white &= !placed;
+            }
+            _ => {
+                self.white |= placed;
+                self.black &= !placed;
+            }
+        }
+    }
+}
+
+/// Game state before a move, restored by `Reversi::undo`
+#[derive(Clone, Debug)]
+struct Snapshot {
+    board: Board,
+    state: States,
+    passed: bool,
+    result: Option<GameResult>,
+    /// length of the history, the next entry is the move that followed
+    history: usize,
+}
+
 #[derive(Debug)]
 pub struct Reversi {
     pub player1_id: Cell<usize>,
     pub player2_id: Cell<usize>,
     pub state: RefCell<States>,
-    pub discs: RefCell<Vec<Vec<u8>>>,
+    pub board: Cell<Board>,
+    /// true if the last move made the opponent pass
+    pub passed: Cell<bool>,
+    /// set when the game is over because neither player can move
+    pub result: RefCell<Option<GameResult>>,
+    /// every move since `init`, including passes
+    pub history: RefCell<Vec<Move>>,
+    snapshots: RefCell<Vec<Snapshot>>,
+}
+
+impl Default for Reversi {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Reversi {
@@ -35,121 +372,320 @@ impl Reversi {
             player1_id: Cell::new(0),
             player2_id: Cell::new(0),
             state: RefCell::new(States::End),
-            discs: RefCell::new(vec![vec![]]),
+            board: Cell::new(Board::default()),
+            passed: Cell::new(false),
+            result: RefCell::new(None),
+            history: RefCell::new(Vec::new()),
+            snapshots: RefCell::new(Vec::new()),
         }
     }
 
     pub fn init(&self) {
-        self.discs.replace(vec![
-            vec![0, 0, 0, 0, 0, 0, 0, 0],
-            vec![0, 0, 0, 0, 0, 0, 0, 0],
-            vec![0, 0, 0, 0, 0, 0, 0, 0],
-            vec![0, 0, 0, 1, 2, 0, 0, 0],
-            vec![0, 0, 0, 2, 1, 0, 0, 0],
-            vec![0, 0, 0, 0, 0, 0, 0, 0],
-            vec![0, 0, 0, 0, 0, 0, 0, 0],
-            vec![0, 0, 0, 0, 0, 0, 0, 0],
-        ]);
+        self.init_with_size(DEFAULT_SIZE);
+    }
+
+    /// Start a game on a `size` x `size` board, see `Geometry::new`
+    pub fn init_with_size(&self, size: usize) {
+        self.board.set(Board::start(size));
         self.state.replace(States::TurnPlayer1);
+        self.passed.set(false);
+        self.result.replace(None);
+        self.history.borrow_mut().clear();
+        self.snapshots.borrow_mut().clear();
     }
 
     pub fn end(&self) {
-        self.discs.replace(vec![vec![0; 8]; 8]);
+        self.board.set(Board::empty(self.board.get().size()));
         self.state.replace(States::End);
+        self.passed.set(false);
+        self.result.replace(None);
+        self.history.borrow_mut().clear();
+        self.snapshots.borrow_mut().clear();
     }
 
-    pub fn set_disc(&self, id: usize, _x: usize, _y: usize) -> Result<(), ()> {
-        if _x > 7 || _y > 7 {
-            return Err(());
+    pub fn set_disc(&self, id: usize, x: usize, y: usize) -> Result<(), SetDiscError> {
+        let mut board = self.board.get();
+        if x >= board.size() || y >= board.size() {
+            return Err(SetDiscError::OutOfBounds);
         }
-        let id1 = self.player1_id.get();
-        let id2 = self.player2_id.get();
-        let mut disc = Disc::None;
-        if id == id1 {
-            disc = Disc::White;
-            if self.state.clone().into_inner() != States::TurnPlayer1 {
-                return Err(());
-            }
+        let disc = match self.turn_disc(id) {
+            Some(disc) => disc,
+            None => return Err(SetDiscError::NotYourTurn),
+        };
+        let square = board.geometry.square(x, y);
+        let (own, opp) = board.sides(&disc);
+        if (own | opp) & 1 << square != 0 {
+            return Err(SetDiscError::Occupied);
         }
-        if id == id2 {
-            disc = Disc::Black;
-            if self.state.clone().into_inner() != States::TurnPlayer2 {
-                return Err(());
-            }
+        let flips = board.geometry.flips(own, opp, square);
+        if flips == 0 {
+            return Err(SetDiscError::NoFlips);
         }
-        if disc != Disc::None {
-            let mut discs = self.discs.clone();
-            if discs.get_mut()[_y][_x] != 0 {
-                return Err(());
-            }
-            discs.get_mut()[_y][_x] = disc.u8();
-            self.discs.replace(discs.into_inner());
-            self.update_discs(_x, _y);
-            match disc {
-                Disc::White => {
-                    self.state.replace(States::TurnPlayer2);
-                }
-                Disc::Black => {
-                    self.state.replace(States::TurnPlayer1);
-                }
-                _ => (),
+        self.snapshots.borrow_mut().push(Snapshot {
+            board: self.board.get(),
+            state: self.state.borrow().clone(),
+            passed: self.passed.get(),
+            result: self.result.borrow().clone(),
+            history: self.history.borrow().len(),
+        });
+        board.put(&disc, square, flips);
+        self.board.set(board);
+        let player = if disc == Disc::White { 1 } else { 2 };
+        self.history
+            .borrow_mut()
+            .push(Move::new(player, id, Some((x, y))));
+        self.next_turn(&disc);
+        Ok(())
+    }
+
+    /// Hand the turn to the opponent of `disc`.
+    /// The opponent passes if it has no legal move, and the game is over
+    /// if neither player can move.
+    fn next_turn(&self, disc: &Disc) {
+        let (next, again, opponent) = match disc {
+            Disc::White => (States::TurnPlayer2, States::TurnPlayer1, 2),
+            _ => (States::TurnPlayer1, States::TurnPlayer2, 1),
+        };
+        let board = self.board.get();
+        let (own, opp) = board.sides(disc);
+        self.passed.set(false);
+        if board.geometry.moves(opp, own) != 0 {
+            self.state.replace(next);
+        } else if board.geometry.moves(own, opp) != 0 {
+            let opponent_id = if opponent == 1 {
+                self.player1_id.get()
+            } else {
+                self.player2_id.get()
             };
-            Ok(())
+            self.history
+                .borrow_mut()
+                .push(Move::new(opponent, opponent_id, None));
+            self.passed.set(true);
+            self.state.replace(again);
         } else {
-            Err(())
+            self.state.replace(States::End);
+            self.result.replace(Some(self.score()));
+        }
+    }
+
+    /// Disc counts and winner of the current board
+    pub fn score(&self) -> GameResult {
+        let board = self.board.get();
+        let player1 = board.white.count_ones() as usize;
+        let player2 = board.black.count_ones() as usize;
+        let winner = if player1 > player2 {
+            Winner::Player1
+        } else if player2 > player1 {
+            Winner::Player2
+        } else {
+            Winner::Draw
+        };
+        GameResult {
+            winner,
+            player1,
+            player2,
+            reason: EndReason::Completed,
         }
     }
 
+    /// End the running game with `winner`, returns false if no game is running
+    fn finish(&self, winner: Winner, reason: EndReason) -> bool {
+        if *self.state.borrow() == States::End {
+            return false;
+        }
+        let result = GameResult {
+            winner,
+            reason,
+            ..self.score()
+        };
+        self.state.replace(States::End);
+        self.passed.set(false);
+        self.result.replace(Some(result));
+        true
+    }
+
     pub fn get_discs(&self) -> Vec<Vec<u8>> {
-        let discs = self.discs.clone();
-        discs.into_inner()
+        self.board.get().to_discs()
+    }
+
+    pub fn history(&self) -> History {
+        let moves = self.history.borrow().clone();
+        let size = self.board.get().size();
+        let transcript = moves
+            .iter()
+            .filter(|m| m.square.is_some())
+            .map(|m| m.notation(size))
+            .collect();
+        History {
+            size,
+            moves,
+            transcript,
+        }
     }
 
-    fn update_discs(&self, x: usize, y: usize) {
-        let mut discs = self.discs.clone().into_inner();
-        let disc = match discs[y][x] {
-            1 => Disc::White,
-            2 => Disc::Black,
-            _ => Disc::None,
+    /// Plays `moves` from the starting position with the player ids 1 and 2.
+    /// Each move is made by the player to move, passes are automatic.
+    pub fn replay(moves: &[(usize, usize)]) -> Result<Reversi, RecordError> {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        r.init();
+        for (index, (x, y)) in moves.iter().enumerate() {
+            let id = match *r.state.borrow() {
+                States::TurnPlayer1 => 1,
+                States::TurnPlayer2 => 2,
+                States::End => 0,
+            };
+            r.set_disc(id, *x, *y)
+                .map_err(|error| RecordError::IllegalMove { index, error })?;
+        }
+        Ok(r)
+    }
+
+    pub fn last_move(&self) -> Option<Move> {
+        self.history.borrow().last().cloned()
+    }
+
+    /// Every legal move of the player to move, empty if the game is over
+    pub fn legal_moves(&self) -> Vec<LegalMove> {
+        let disc = match *self.state.borrow() {
+            States::TurnPlayer1 => Disc::White,
+            States::TurnPlayer2 => Disc::Black,
+            States::End => return Vec::new(),
         };
-        if disc == Disc::None {
-            return;
+        let board = self.board.get();
+        let g = board.geometry;
+        let (own, opp) = board.sides(&disc);
+        g.squares(g.moves(own, opp))
+            .into_iter()
+            .map(|(x, y)| LegalMove {
+                x,
+                y,
+                flips: g.squares(g.flips(own, opp, g.square(x, y))),
+            })
+            .collect()
+    }
+
+    /// Disc of the player `id` if it is that player's turn
+    fn turn_disc(&self, id: usize) -> Option<Disc> {
+        let state = self.state.borrow();
+        if id != 0 && id == self.player1_id.get() && *state == States::TurnPlayer1 {
+            return Some(Disc::White);
         }
-        fn update(discs: &mut Vec<Vec<u8>>, disc: &Disc, x: i8, y: i8, addx: i8, addy: i8) -> bool {
-            if x < 0 || x > 7 || y < 0 || y > 7 {
-                return false;
-            }
-            let (x, y) = (x as usize, y as usize);
-            let old_disc = discs[y][x];
-            if discs[y][x] != Disc::None as u8 && discs[y][x] != disc.u8() {
-                discs[y][x] = disc.u8();
-            } else if discs[y][x] == disc.u8() {
-                return true;
-            } else {
-                return false;
-            }
-            let result = update(discs, &disc, x as i8 + addx, y as i8 + addy, addx, addy);
-            if !result {
-                discs[y][x] = old_disc;
+        if id != 0 && id == self.player2_id.get() && *state == States::TurnPlayer2 {
+            return Some(Disc::Black);
+        }
+        None
+    }
+}
+
+impl Game for Reversi {
+    fn kind(&self) -> &'static str {
+        "reversi"
+    }
+
+    fn player(&self, seat: Player) -> usize {
+        match seat {
+            Player::One => self.player1_id.get(),
+            Player::Two => self.player2_id.get(),
+        }
+    }
+
+    fn set_player(&self, seat: Player, id: usize) {
+        match seat {
+            Player::One => self.player1_id.set(id),
+            Player::Two => self.player2_id.set(id),
+        }
+    }
+
+    fn start(&self, size: Option<usize>) -> Result<(), String> {
+        let size = size.unwrap_or(DEFAULT_SIZE);
+        if !Geometry::valid(size) {
+            return Err(format!(
+                "unsupported board size {}, reversi boards are even sizes from {} to {}",
+                size, MIN_SIZE, MAX_SIZE
+            ));
+        }
+        self.init_with_size(size);
+        Ok(())
+    }
+
+    fn play(&self, id: usize, x: usize, y: usize) -> Result<(), SetDiscError> {
+        self.set_disc(id, x, y)
+    }
+
+    fn state(&self) -> States {
+        self.state.borrow().clone()
+    }
+
+    fn result(&self) -> Option<GameResult> {
+        self.result.borrow().clone()
+    }
+
+    fn to_json(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        map.insert("state".to_owned(), json!(self.state()));
+        map.insert("passed".to_owned(), json!(self.passed.get()));
+        map.insert("moves".to_owned(), json!(self.legal_moves()));
+        map.insert("last_move".to_owned(), json!(self.last_move()));
+        map.insert("data".to_owned(), json!(self.get_discs()));
+        map
+    }
+
+    fn forfeit(&self, id: usize, reason: EndReason) -> bool {
+        let winner = match self.seat_of(id) {
+            Some(Player::One) => Winner::Player2,
+            Some(Player::Two) => Winner::Player1,
+            None => return false,
+        };
+        self.finish(winner, reason)
+    }
+
+    fn agree_draw(&self) -> bool {
+        self.finish(Winner::Draw, EndReason::Agreement)
+    }
+
+    fn can_undo(&self, id: usize) -> bool {
+        id != 0
+            && self
+                .history
+                .borrow()
+                .iter()
+                .any(|m| m.player_id == id && m.square.is_some())
+    }
+
+    fn undo(&self, id: usize) -> bool {
+        if !self.can_undo(id) {
+            return false;
+        }
+        let mut snapshots = self.snapshots.borrow_mut();
+        let mut history = self.history.borrow_mut();
+        while let Some(snapshot) = snapshots.pop() {
+            let mover = history[snapshot.history].player_id;
+            if mover != id {
+                continue;
             }
-            result
+            history.truncate(snapshot.history);
+            self.board.set(snapshot.board);
+            self.state.replace(snapshot.state);
+            self.passed.set(snapshot.passed);
+            self.result.replace(snapshot.result);
+            return true;
         }
-        let (x, y) = (x as i8, y as i8);
-        update(&mut discs, &disc, x + 1, y, 1, 0);
-        update(&mut discs, &disc, x - 1, y, -1, 0);
-        update(&mut discs, &disc, x, y + 1, 0, 1);
-        update(&mut discs, &disc, x, y - 1, 0, -1);
-        update(&mut discs, &disc, x + 1, y + 1, 1, 1);
-        update(&mut discs, &disc, x - 1, y - 1, -1, -1);
-        update(&mut discs, &disc, x + 1, y - 1, 1, -1);
-        update(&mut discs, &disc, x - 1, y + 1, -1, 1);
-        self.discs.replace(discs);
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Reversi;
+    use super::{
+        utc, Board, EndReason, Geometry, LegalMove, Reversi, SetDiscError, States, Winner,
+    };
+    use crate::game::Game;
 
     #[test]
     fn reversi() {
@@ -157,16 +693,271 @@ mod tests {
         r.player1_id.set(1);
         r.player2_id.set(2);
         r.init();
-        r.set_disc(1, 0, 4).unwrap();
-        r.set_disc(2, 2, 4).unwrap();
-        r.set_disc(1, 2, 3).unwrap();
-        r.set_disc(2, 1, 4).unwrap();
-        r.set_disc(1, 5, 4).unwrap();
-        r.set_disc(2, 6, 4).unwrap();
-        r.set_disc(1, 7, 4).unwrap();
-        r.set_disc(2, 2, 5).unwrap();
-        for r in r.discs.into_inner().iter() {
+        // f5 d6 c3 d3 c4
+        r.set_disc(1, 2, 4).unwrap();
+        r.set_disc(2, 4, 5).unwrap();
+        r.set_disc(1, 5, 2).unwrap();
+        r.set_disc(2, 4, 2).unwrap();
+        r.set_disc(1, 5, 3).unwrap();
+        for r in r.get_discs().iter() {
             println!("{:?}", r);
         }
+        let history = r.history();
+        assert_eq!(history.transcript, "f5d6c3d3c4");
+        assert_eq!(history.moves[1].player, 2);
+        assert_eq!(history.moves[1].player_id, 2);
+        assert_eq!(r.last_move().unwrap().notation(8), "c4");
+    }
+
+    #[test]
+    fn illegal_moves() {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        assert_eq!(r.set_disc(1, 2, 4), Err(SetDiscError::NotYourTurn));
+        r.init();
+        assert_eq!(r.set_disc(1, 8, 0), Err(SetDiscError::OutOfBounds));
+        assert_eq!(r.set_disc(2, 2, 4), Err(SetDiscError::NotYourTurn));
+        assert_eq!(r.set_disc(3, 2, 4), Err(SetDiscError::NotYourTurn));
+        assert_eq!(r.set_disc(1, 3, 3), Err(SetDiscError::Occupied));
+        assert_eq!(r.set_disc(1, 7, 4), Err(SetDiscError::NoFlips));
+        assert_eq!(r.set_disc(1, 2, 5), Err(SetDiscError::NoFlips));
+        assert_eq!(r.set_disc(1, 2, 4), Ok(()));
+        // player1 plays the 1 discs as it did before bitboards
+        assert_eq!(r.get_discs()[4][3], 1);
+    }
+
+    #[test]
+    fn legal_moves() {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        assert!(r.legal_moves().is_empty());
+        r.init();
+        let moves = r.legal_moves();
+        assert_eq!(moves.len(), 4);
+        assert!(moves.contains(&LegalMove {
+            x: 2,
+            y: 4,
+            flips: vec![(3, 4)],
+        }));
+        for m in moves {
+            let r2 = Reversi::new();
+            r2.player1_id.set(1);
+            r2.init();
+            assert_eq!(r2.set_disc(1, m.x, m.y), Ok(()));
+        }
+    }
+
+    #[test]
+    fn pass_and_game_over() {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        r.init();
+        let mut discs = vec![vec![1; 8]; 8];
+        discs[0][0] = 0;
+        discs[0][1] = 2;
+        discs[7][7] = 0;
+        discs[7][6] = 2;
+        r.board.set(Board::from_discs(&discs));
+
+        // player2 has no legal move after this, so player1 moves again
+        r.set_disc(1, 0, 0).unwrap();
+        assert!(r.passed.get());
+        assert_eq!(r.last_move().unwrap().notation(8), "pass");
+        assert_eq!(r.last_move().unwrap().player, 2);
+        assert_eq!(*r.state.borrow(), States::TurnPlayer1);
+        assert!(r.result.borrow().is_none());
+
+        // the board is full, neither player can move
+        r.set_disc(1, 7, 7).unwrap();
+        assert!(!r.passed.get());
+        assert_eq!(*r.state.borrow(), States::End);
+        let result = r.result.borrow().clone().unwrap();
+        assert_eq!(result.winner, Winner::Player1);
+        assert_eq!((result.player1, result.player2), (64, 0));
+        assert_eq!(r.history().transcript, "h1a8");
+        assert_eq!(r.history().moves.len(), 3);
+    }
+
+    #[test]
+    fn resign_and_draw() {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        assert!(!r.resign(1));
+        r.init();
+        r.set_disc(1, 2, 4).unwrap();
+        assert!(!r.resign(3));
+        assert!(r.resign(1));
+        let result = r.result.borrow().clone().unwrap();
+        assert_eq!(result.winner, Winner::Player2);
+        assert_eq!(result.reason, EndReason::Resignation);
+        assert_eq!((result.player1, result.player2), (4, 1));
+        assert_eq!(*r.state.borrow(), States::End);
+        assert!(!r.resign(2));
+
+        r.init();
+        assert!(r.abandon(2));
+        assert_eq!(r.result.borrow().as_ref().unwrap().winner, Winner::Player1);
+
+        r.init();
+        assert!(r.time_out(1));
+        let result = r.result.borrow().clone().unwrap();
+        assert_eq!(result.winner, Winner::Player2);
+        assert_eq!(result.reason, EndReason::Timeout);
+
+        r.init();
+        assert!(r.agree_draw());
+        let result = r.result.borrow().clone().unwrap();
+        assert_eq!(result.winner, Winner::Draw);
+        assert_eq!(result.reason, EndReason::Agreement);
+        assert!(!r.agree_draw());
+    }
+
+    #[test]
+    fn draw() {
+        let r = Reversi::new();
+        r.init();
+        r.board
+            .set(Board::from_discs(&vec![vec![1, 1, 1, 1, 2, 2, 2, 2]; 8]));
+        let result = r.score();
+        assert_eq!(result.winner, Winner::Draw);
+        assert_eq!((result.player1, result.player2), (32, 32));
+    }
+
+    #[test]
+    fn bitboard() {
+        let start = Board::start(8);
+        let g = start.geometry;
+        assert_eq!(start.to_discs()[3][3], 1);
+        assert_eq!(start.to_discs()[3][4], 2);
+        assert_eq!(Board::from_discs(&start.to_discs()), start);
+        // d3 c4 f5 e6
+        assert_eq!(
+            g.moves(start.black, start.white),
+            1 << 19 | 1 << 26 | 1 << 37 | 1 << 44
+        );
+        // moves must not wrap around the edges of the board
+        let mut discs = vec![vec![0; 8]; 8];
+        discs[0][6] = 1;
+        discs[0][7] = 2;
+        discs[2][1] = 1;
+        discs[2][0] = 2;
+        let edge = Board::from_discs(&discs);
+        assert_eq!(g.moves(edge.white, edge.black), 0);
+    }
+
+    #[test]
+    fn board_sizes() {
+        assert!(!Geometry::valid(2));
+        assert!(!Geometry::valid(7));
+        assert!(!Geometry::valid(12));
+        for size in (4..=10).step_by(2) {
+            let start = Board::start(size);
+            let c = size / 2;
+            let discs = start.to_discs();
+            assert_eq!(discs.len(), size);
+            assert_eq!(discs[c - 1][c - 1], 1);
+            assert_eq!(discs[c][c - 1], 2);
+            assert_eq!(Board::from_discs(&discs), start);
+            assert_eq!(g_moves(start).len(), 4);
+        }
+
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        r.init_with_size(6);
+        assert_eq!(r.set_disc(1, 6, 0), Err(SetDiscError::OutOfBounds));
+        // e4 flips d4
+        r.set_disc(1, 1, 3).unwrap();
+        assert_eq!(r.history().transcript, "e4");
+        assert_eq!(r.history().size, 6);
+        assert_eq!(r.get_discs().len(), 6);
+
+        // play 10x10 to the end, every legal move stays on the board
+        r.init_with_size(10);
+        while let Some(m) = r.legal_moves().into_iter().next() {
+            let id = if *r.state.borrow() == States::TurnPlayer1 {
+                1
+            } else {
+                2
+            };
+            assert!(m.x < 10 && m.y < 10);
+            r.set_disc(id, m.x, m.y).unwrap();
+        }
+        let result = r.result.borrow().clone().unwrap();
+        assert!(result.player1 + result.player2 <= 100);
+        r.end();
+        assert_eq!(r.get_discs().len(), 10);
+    }
+
+    fn g_moves(board: Board) -> Vec<(usize, usize)> {
+        let g = board.geometry;
+        g.squares(g.moves(board.black, board.white))
+    }
+
+    #[test]
+    fn undo() {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        r.init();
+        assert!(!r.undo(1));
+        r.set_disc(1, 2, 4).unwrap();
+        let after_f5 = r.board.get();
+        r.set_disc(2, 4, 5).unwrap();
+        r.set_disc(1, 5, 2).unwrap();
+        assert!(!r.undo(3));
+
+        // player2 takes back d6, c3 goes with it
+        assert!(r.undo(2));
+        assert_eq!(r.board.get(), after_f5);
+        assert_eq!(*r.state.borrow(), States::TurnPlayer2);
+        assert_eq!(r.history().transcript, "f5");
+
+        // player1 takes back f5
+        assert!(r.undo(1));
+        assert_eq!(r.board.get(), Board::start(8));
+        assert_eq!(*r.state.borrow(), States::TurnPlayer1);
+        assert!(r.history().moves.is_empty());
+        assert!(!r.undo(1));
+    }
+
+    #[test]
+    fn undo_pass_and_game_over() {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        r.init();
+        let mut discs = vec![vec![1; 8]; 8];
+        discs[0][0] = 0;
+        discs[0][1] = 2;
+        discs[7][7] = 0;
+        discs[7][6] = 2;
+        let board = Board::from_discs(&discs);
+        r.board.set(board);
+        r.set_disc(1, 0, 0).unwrap();
+        r.set_disc(1, 7, 7).unwrap();
+        assert!(r.result.borrow().is_some());
+
+        assert!(r.undo(1));
+        assert!(r.passed.get());
+        assert!(r.result.borrow().is_none());
+        assert_eq!(*r.state.borrow(), States::TurnPlayer1);
+        assert_eq!(r.history().moves.len(), 2);
+
+        assert!(r.undo(1));
+        assert_eq!(r.board.get(), board);
+        assert!(!r.passed.get());
+        assert!(r.history().moves.is_empty());
+    }
+
+    #[test]
+    fn utc_date() {
+        assert_eq!(utc(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(utc(951_782_400_000), (2000, 2, 29, 0, 0, 0));
+        assert_eq!(utc(1_792_321_845_000), (2026, 10, 18, 11, 10, 45));
     }
 }
@@ -0,0 +1,338 @@
+//! Generic Game Format, the text records of GGS and most Othello software:
+//! `(;GM[Othello]PB[alice]PW[bob]TY[8]BO[...]B[f5]W[d6];)`
+
+use std::fmt;
+
+use super::{
+    mirror, parse_square, utc, Board, GameResult, Geometry, History, RecordError, Reversi,
+    DEFAULT_SIZE,
+};
+
+/// BO value of the starting position, black to move
+fn start(size: usize) -> String {
+    let mut bo = size.to_string();
+    for row in Board::start(size).to_discs() {
+        bo.push(' ');
+        bo.extend(row.iter().rev().map(|d| match d {
+            1 => '*',
+            2 => 'O',
+            _ => '-',
+        }));
+    }
+    bo.push_str(" *");
+    bo
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GgfMove {
+    /// true for black (player1), false for white (player2)
+    pub black: bool,
+    /// (x, y) of the disc as written in the record, `None` for a pass
+    pub square: Option<(usize, usize)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Game {
+    /// board size, the TY value
+    pub size: usize,
+    /// name of black (player1)
+    pub black: String,
+    /// name of white (player2)
+    pub white: String,
+    /// such as "2026.10.18_11:10:45.GMT"
+    pub date: Option<String>,
+    pub place: Option<String>,
+    /// black's discs minus white's discs
+    pub result: Option<f64>,
+    pub moves: Vec<GgfMove>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game {
+            size: DEFAULT_SIZE,
+            black: String::new(),
+            white: String::new(),
+            date: None,
+            place: None,
+            result: None,
+            moves: Vec::new(),
+        }
+    }
+}
+
+impl Game {
+    /// Record of a game played on the server
+    pub fn from_history(
+        history: &History,
+        black: &str,
+        white: &str,
+        result: Option<&GameResult>,
+    ) -> Self {
+        let date = history.moves.first().map(|m| {
+            let (y, mo, d, h, mi, s) = utc(m.time);
+            format!("{}.{:02}.{:02}_{:02}:{:02}:{:02}.GMT", y, mo, d, h, mi, s)
+        });
+        Game {
+            size: history.size,
+            black: black.to_owned(),
+            white: white.to_owned(),
+            date,
+            place: Some("ws-room-test".to_owned()),
+            result: result.map(|r| r.player1 as f64 - r.player2 as f64),
+            moves: history
+                .moves
+                .iter()
+                .map(|m| GgfMove {
+                    black: m.player == 1,
+                    square: m.square.map(|s| mirror(history.size, s)),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Game, RecordError> {
+        let body = s
+            .trim()
+            .strip_prefix("(;")
+            .and_then(|s| s.strip_suffix(";)"))
+            .ok_or_else(|| RecordError::Format("a game must be enclosed in (; ;)".to_owned()))?;
+        let mut game = Game::default();
+        let mut board = None;
+        let mut rest = body.trim_start();
+        while !rest.is_empty() {
+            let open = rest
+                .find('[')
+                .ok_or_else(|| RecordError::Format(format!("missing '[' in {:?}", rest)))?;
+            let key = rest[..open].trim();
+            let (value, len) = read_value(&rest[open + 1..])?;
+            rest = rest[open + 1 + len..].trim_start();
+            match key {
+                "GM" if !value.eq_ignore_ascii_case("othello") => {
+                    return Err(RecordError::Unsupported(format!("game {}", value)));
+                }
+                "TY" => match value.trim().parse() {
+                    Ok(size) if Geometry::valid(size) => game.size = size,
+                    _ => return Err(RecordError::Unsupported(format!("game type {}", value))),
+                },
+                "BO" => board = Some(value.split_whitespace().collect::<Vec<_>>().join(" ")),
+                "PB" => game.black = value,
+                "PW" => game.white = value,
+                "DT" => game.date = Some(value),
+                "PC" => game.place = Some(value),
+                "RE" => {
+                    let score = value.split(':').next().unwrap_or("");
+                    game.result =
+                        Some(score.trim().parse().map_err(|_| {
+                            RecordError::Format(format!("invalid result {:?}", value))
+                        })?);
+                }
+                "B" | "W" => {
+                    let coord = value.split('/').next().unwrap_or("").trim();
+                    let square = if coord.eq_ignore_ascii_case("pa") {
+                        None
+                    } else {
+                        Some(parse_square(coord).ok_or_else(|| {
+                            RecordError::Format(format!("invalid move {:?}", value))
+                        })?)
+                    };
+                    game.moves.push(GgfMove {
+                        black: key == "B",
+                        square,
+                    });
+                }
+                _ => {}
+            }
+        }
+        if let Some(board) = board {
+            if board != start(game.size) {
+                return Err(RecordError::Unsupported(format!("board {}", board)));
+            }
+        }
+        game.check_squares()?;
+        Ok(game)
+    }
+
+    /// Every move must be on the board, TY may come after the moves
+    fn check_squares(&self) -> Result<(), RecordError> {
+        for (index, m) in self.moves.iter().enumerate() {
+            match m.square {
+                Some((x, y)) if x >= self.size || y >= self.size => {
+                    return Err(RecordError::Format(format!(
+                        "move #{} is off the {}x{} board",
+                        index + 1,
+                        self.size,
+                        self.size
+                    )));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses every game of a file, one or more records
+    pub fn parse_all(s: &str) -> Result<Vec<Game>, RecordError> {
+        s.split("(;")
+            .filter(|g| !g.trim().is_empty())
+            .map(|g| Game::parse(&format!("(;{}", g)))
+            .collect()
+    }
+
+    /// Plays the record through `Reversi::set_disc` with black as player id 1
+    /// and white as player id 2
+    pub fn replay(&self) -> Result<Reversi, RecordError> {
+        self.check_squares()?;
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        r.init_with_size(self.size);
+        for (index, m) in self.moves.iter().enumerate() {
+            // passes are made by the engine itself
+            if let Some((x, y)) = m.square.map(|s| mirror(self.size, s)) {
+                let id = if m.black { 1 } else { 2 };
+                r.set_disc(id, x, y)
+                    .map_err(|error| RecordError::IllegalMove { index, error })?;
+            }
+        }
+        Ok(r)
+    }
+}
+
+/// Reads a property value up to the closing ']',
+/// returns the value and the length consumed including the ']'
+fn read_value(s: &str) -> Result<(String, usize), RecordError> {
+    let mut value = String::new();
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => {
+                value.push(c);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            ']' => return Ok((value, i + 1)),
+            _ => value.push(c),
+        }
+    }
+    Err(RecordError::Format("missing ']'".to_owned()))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(;GM[Othello]")?;
+        if let Some(place) = &self.place {
+            write!(f, "PC[{}]", escape(place))?;
+        }
+        if let Some(date) = &self.date {
+            write!(f, "DT[{}]", escape(date))?;
+        }
+        write!(f, "PB[{}]PW[{}]", escape(&self.black), escape(&self.white))?;
+        if let Some(result) = self.result {
+            write!(f, "RE[{:+.3}]", result)?;
+        }
+        write!(f, "TY[{}]BO[{}]", self.size, start(self.size))?;
+        for m in &self.moves {
+            let coord = match m.square {
+                Some((x, y)) => format!("{}{}", (b'a' + x as u8) as char, y + 1),
+                None => "PA".to_owned(),
+            };
+            write!(f, "{}[{}]", if m.black { "B" } else { "W" }, coord)?;
+        }
+        write!(f, ";)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Game, GgfMove};
+    use crate::ai::{best_move, Level};
+    use crate::reversi::{RecordError, Reversi, SetDiscError, States};
+
+    #[test]
+    fn parse() {
+        let game = Game::parse(
+            "(;GM[Othello]PC[GGS/os]DT[2003.01.01_00:00:00.GMT]PB[alice]PW[b\\]ob]\
+             RE[-2.000:r]TY[8]BO[8 -------- -------- -------- ---O*--- ---*O--- \
+             -------- -------- -------- *]B[f5//1.5]W[D6/-0.5/2]B[c3];)",
+        )
+        .unwrap();
+        assert_eq!(game.black, "alice");
+        assert_eq!(game.white, "b]ob");
+        assert_eq!(game.result, Some(-2.0));
+        assert_eq!(game.moves.len(), 3);
+        assert_eq!(game.moves[1].square, Some((3, 5)));
+        let r = game.replay().unwrap();
+        assert_eq!(r.history().transcript, "f5d6c3");
+
+        assert!(Game::parse("(;GM[Chess];)").is_err());
+        assert!(Game::parse("(;GM[Othello]TY[8r];)").is_err());
+        assert!(Game::parse("(;GM[Othello]TY[8]BO[8 -------- *];)").is_err());
+        assert!(Game::parse("(;GM[Othello]B[f0];)").is_err());
+        assert!(Game::parse("(;GM[Othello]B[i5];)").is_err());
+        assert!(Game::parse("(;GM[Othello]TY[6]B[h2];)").is_err());
+        assert!(Game::parse("(;GM[Othello]B[h2]TY[6];)").is_err());
+        let off_board = Game {
+            moves: vec![GgfMove {
+                black: true,
+                square: Some((8, 4)),
+            }],
+            ..Game::default()
+        };
+        assert!(off_board.replay().is_err());
+        assert_eq!(
+            Game::parse("(;GM[Othello]B[f5]B[d6];)")
+                .unwrap()
+                .replay()
+                .err(),
+            Some(RecordError::IllegalMove {
+                index: 1,
+                error: SetDiscError::NotYourTurn
+            })
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        r.init();
+        while *r.state.borrow() != States::End {
+            let (id, player1) = match *r.state.borrow() {
+                States::TurnPlayer1 => (1, true),
+                _ => (2, false),
+            };
+            let (x, y) = best_move(r.board.get(), player1, Level::Easy).unwrap();
+            r.set_disc(id, x, y).unwrap();
+        }
+        let result = r.result.borrow().clone();
+        let game = Game::from_history(&r.history(), "alice", "bob", result.as_ref());
+        let text = game.to_string();
+
+        let games = Game::parse_all(&format!("{}\n{}\n", text, text)).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0], game);
+        let replayed = games[0].replay().unwrap();
+        assert_eq!(replayed.board.get(), r.board.get());
+        assert_eq!(*replayed.result.borrow(), result);
+    }
+
+    #[test]
+    fn board_size() {
+        let game = Game::parse(
+            "(;GM[Othello]TY[6]BO[6 ------ ------ --O*-- --*O-- ------ ------ *]B[e4];)",
+        )
+        .unwrap();
+        assert_eq!(game.size, 6);
+        let r = game.replay().unwrap();
+        assert_eq!(r.get_discs().len(), 6);
+        let text = Game::from_history(&r.history(), "a", "b", None).to_string();
+        assert!(text.contains("TY[6]"));
+        assert_eq!(Game::parse(&text).unwrap().moves, game.moves);
+    }
+}
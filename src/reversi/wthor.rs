@@ -0,0 +1,239 @@
+//! WTHOR, the binary game database of the French Othello federation.
+//! A file is a 16 byte header followed by 68 byte game records.
+//! Player and tournament names live in separate files and are referred to
+//! by number. Only 8x8 games can be stored.
+
+use super::{mirror, utc, GameResult, History, RecordError, Reversi, DEFAULT_SIZE};
+
+const HEADER_LEN: usize = 16;
+const GAME_LEN: usize = 68;
+/// Moves stored per game, unused slots are 0
+const MAX_MOVES: usize = 60;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Game {
+    pub tournament: u16,
+    /// number of black (player1) in the player file
+    pub black: u16,
+    /// number of white (player2) in the player file
+    pub white: u16,
+    /// black's discs at the end of the game
+    pub black_discs: u8,
+    /// black's discs with perfect play from the end of the recorded search
+    pub theoretical_discs: u8,
+    /// (x, y) of every disc as written in the record, passes are not recorded
+    pub moves: Vec<(usize, usize)>,
+}
+
+impl Game {
+    /// Record of a game played on the server
+    pub fn from_history(
+        history: &History,
+        black: u16,
+        white: u16,
+        result: Option<&GameResult>,
+    ) -> Result<Self, RecordError> {
+        if history.size != DEFAULT_SIZE {
+            return Err(RecordError::Unsupported(format!(
+                "board size {}",
+                history.size
+            )));
+        }
+        let black_discs = result.map_or(0, |r| r.player1 as u8);
+        Ok(Game {
+            tournament: 0,
+            black,
+            white,
+            black_discs,
+            theoretical_discs: black_discs,
+            moves: history
+                .moves
+                .iter()
+                .filter_map(|m| m.square)
+                .map(|s| mirror(DEFAULT_SIZE, s))
+                .collect(),
+        })
+    }
+
+    /// Plays the record through `Reversi::set_disc`
+    pub fn replay(&self) -> Result<Reversi, RecordError> {
+        let moves: Vec<_> = self
+            .moves
+            .iter()
+            .map(|s| mirror(DEFAULT_SIZE, *s))
+            .collect();
+        Reversi::replay(&moves)
+    }
+
+    fn parse(b: &[u8]) -> Result<Game, RecordError> {
+        let mut moves = Vec::new();
+        for m in &b[8..8 + MAX_MOVES] {
+            let (x, y) = (m % 10, m / 10);
+            match (x, y) {
+                (0, 0) => break,
+                (1..=8, 1..=8) => moves.push((x as usize - 1, y as usize - 1)),
+                _ => return Err(RecordError::Format(format!("invalid move {}", m))),
+            }
+        }
+        Ok(Game {
+            tournament: u16::from_le_bytes([b[0], b[1]]),
+            black: u16::from_le_bytes([b[2], b[3]]),
+            white: u16::from_le_bytes([b[4], b[5]]),
+            black_discs: b[6],
+            theoretical_discs: b[7],
+            moves,
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.tournament.to_le_bytes());
+        out.extend_from_slice(&self.black.to_le_bytes());
+        out.extend_from_slice(&self.white.to_le_bytes());
+        out.push(self.black_discs);
+        out.push(self.theoretical_discs);
+        for i in 0..MAX_MOVES {
+            out.push(match self.moves.get(i) {
+                Some((x, y)) => (y * 10 + x + 11) as u8,
+                None => 0,
+            });
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Database {
+    /// (year, month, day) the file was written
+    pub created: (u16, u8, u8),
+    /// year the games were played
+    pub year: u16,
+    /// depth of the search behind `theoretical_discs`
+    pub depth: u8,
+    pub games: Vec<Game>,
+}
+
+impl Database {
+    /// Empty database dated `millis` since the UNIX epoch
+    pub fn new(millis: u64) -> Self {
+        let (year, month, day, _, _, _) = utc(millis);
+        Database {
+            created: (year as u16, month as u8, day as u8),
+            year: year as u16,
+            depth: 0,
+            games: Vec::new(),
+        }
+    }
+
+    pub fn parse(b: &[u8]) -> Result<Database, RecordError> {
+        if b.len() < HEADER_LEN {
+            return Err(RecordError::Format("file is too short".to_owned()));
+        }
+        let count = u32::from_le_bytes([b[4], b[5], b[6], b[7]]) as usize;
+        if b[12] != 0 && b[12] != 8 {
+            return Err(RecordError::Unsupported(format!("board size {}", b[12])));
+        }
+        if b.len() != HEADER_LEN + count * GAME_LEN {
+            return Err(RecordError::Format(format!(
+                "expected {} games in {} bytes",
+                count,
+                b.len()
+            )));
+        }
+        let games = b[HEADER_LEN..]
+            .chunks(GAME_LEN)
+            .map(Game::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Database {
+            created: (b[0] as u16 * 100 + b[1] as u16, b[2], b[3]),
+            year: u16::from_le_bytes([b[10], b[11]]),
+            depth: b[14],
+            games,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.games.len() * GAME_LEN);
+        let (year, month, day) = self.created;
+        out.push((year / 100) as u8);
+        out.push((year % 100) as u8);
+        out.push(month);
+        out.push(day);
+        out.extend_from_slice(&(self.games.len() as u32).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&self.year.to_le_bytes());
+        out.push(8);
+        out.push(0);
+        out.push(self.depth);
+        out.push(0);
+        for game in &self.games {
+            game.write(&mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Database, Game};
+    use crate::ai::{best_move, Level};
+    use crate::reversi::{RecordError, Reversi, SetDiscError, States};
+
+    #[test]
+    fn round_trip() {
+        let r = Reversi::new();
+        r.player1_id.set(1);
+        r.player2_id.set(2);
+        r.init();
+        while *r.state.borrow() != States::End {
+            let (id, player1, level) = match *r.state.borrow() {
+                States::TurnPlayer1 => (1, true, Level::Normal),
+                _ => (2, false, Level::Easy),
+            };
+            let (x, y) = best_move(r.board.get(), player1, level).unwrap();
+            r.set_disc(id, x, y).unwrap();
+        }
+        let result = r.result.borrow().clone();
+        let mut db = Database::new(1_792_321_845_000);
+        db.games
+            .push(Game::from_history(&r.history(), 3, 7, result.as_ref()).unwrap());
+        db.games.push(Game {
+            moves: vec![(5, 4), (3, 5), (2, 2)],
+            ..Game::default()
+        });
+        let bytes = db.to_bytes();
+        assert_eq!(bytes.len(), 16 + 2 * 68);
+        assert_eq!(&bytes[..4], &[20, 26, 10, 18]);
+        assert_eq!(bytes[16 + 68 + 8], 56); // f5
+
+        let parsed = Database::parse(&bytes).unwrap();
+        assert_eq!(parsed, db);
+        let replayed = parsed.games[0].replay().unwrap();
+        assert_eq!(replayed.board.get(), r.board.get());
+        assert_eq!(replayed.score().player1 as u8, parsed.games[0].black_discs);
+        assert_eq!(
+            parsed.games[1].replay().unwrap().history().transcript,
+            "f5d6c3"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Database::parse(&[0; 10]).is_err());
+        let r = Reversi::new();
+        r.init_with_size(6);
+        assert!(Game::from_history(&r.history(), 0, 0, None).is_err());
+        let mut db = Database::new(0);
+        db.games.push(Game {
+            moves: vec![(0, 0)],
+            ..Game::default()
+        });
+        let bytes = db.to_bytes();
+        assert!(Database::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert_eq!(
+            Database::parse(&bytes).unwrap().games[0].replay().err(),
+            Some(RecordError::IllegalMove {
+                index: 0,
+                error: SetDiscError::NoFlips
+            })
+        );
+    }
+}
@@ -4,24 +4,65 @@
 
 use actix::prelude::*;
 use rand::{self, rngs::ThreadRng, Rng};
-use serde::Serialize;
-use serde_json::json;
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::reversi::Reversi;
+use crate::ai::{Ai, Level, Think};
+use crate::clock::{Clock, TimeControl};
+use crate::game::{self, Game, SetDiscError, States};
+use crate::invite::Invite;
+use crate::password::{Hash, Hasher, PasswordHash, Verify};
+use crate::protocol::Event;
+use crate::reversi::{History, LegalMove, Reversi};
+
+pub use crate::game::Player;
+
+/// How often the game clocks are checked for a flagged player
+const CLOCK_TICK: Duration = Duration::from_millis(100);
+/// How often empty rooms are looked for
+const REAP_INTERVAL: Duration = Duration::from_secs(10);
+/// How long an empty room is kept by default
+pub const ROOM_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// How long an invite is valid by default
+pub const INVITE_TTL: Duration = Duration::from_secs(3600);
+/// Longest validity `CreateInvite` accepts, 30 days
+pub const MAX_INVITE_TTL: Duration = Duration::from_secs(30 * 24 * 3600);
+/// Chat messages kept per room
+pub const CHAT_HISTORY: usize = 200;
+/// Chat messages sent on entering a room and per page of `GetChat`
+pub const CHAT_PAGE: usize = 20;
+
+/// Seconds since the UNIX epoch
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
 
 /// Chat server sends this messages to session
 #[derive(Message)]
 pub struct Message(pub String);
 
-/// Message for chat server communications
+// Message for chat server communications
+
+/// Server moved the session into `room`, from the waiting list or out of
+/// a room it was kicked from
+#[derive(Message)]
+pub struct Joined {
+    pub room: String,
+}
 
 /// New chat session is created
 #[derive(Message)]
 #[rtype(usize)]
 pub struct Connect {
     pub addr: Recipient<Message>, // 親アクター（クライアント）のアドレス
+    pub joined: Recipient<Joined>,
+    /// address the client connected from, bans stick to it
+    pub peer: Option<IpAddr>,
 }
 
 /// Session is disconnected
@@ -30,7 +71,8 @@ pub struct Disconnect {
     pub id: usize,
 }
 
-/// Send message to specific room
+/// Send message to specific room. Everyone there gets it as a `chat` event,
+/// the sender too, with the id and time the server gave it.
 #[derive(Message)]
 pub struct ClientMessage {
     /// Id of the client session
@@ -41,101 +83,988 @@ pub struct ClientMessage {
     pub room: String,
 }
 
-/// List of available rooms
-pub struct ListRooms;
+/// Most rooms `ListRooms` returns at once
+pub const MAX_LIST_LIMIT: usize = 100;
+
+/// List of available rooms, a page of them sorted by name
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ListRooms {
+    /// Client id, gets `room_closed` events from now on
+    pub id: usize,
+    /// only rooms with an empty seat at the table that can be entered
+    pub open_seats: bool,
+    /// only rooms where a game is running
+    pub in_progress: bool,
+    /// rooms to skip
+    pub offset: usize,
+    /// page size, `None` or more than `MAX_LIST_LIMIT` for `MAX_LIST_LIMIT`
+    pub limit: Option<usize>,
+}
 
 impl actix::Message for ListRooms {
-    type Result = Vec<String>;
+    type Result = RoomList;
+}
+
+/// A room as `ListRooms` shows it
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RoomInfo {
+    pub name: String,
+    pub members: usize,
+    pub capacity: Option<usize>,
+    /// game type of the table, `None` for a room without one
+    pub game: Option<&'static str>,
+    pub in_progress: bool,
+    /// names of the players in seat 1 and 2, `None` for an empty seat
+    pub players: [Option<String>; 2],
+    /// members who don't play
+    pub spectators: usize,
+}
+
+/// Result of `ListRooms`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RoomList {
+    pub rooms: Vec<RoomInfo>,
+    /// number of rooms matching the filters, on all pages
+    pub total: usize,
+    pub offset: usize,
+}
+
+/// Who can find and enter a room
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// shown by `ListRooms`
+    Public,
+    /// hidden from `ListRooms`, anyone who knows the name can join
+    Unlisted,
+    /// hidden from `ListRooms`, joining needs the password
+    Private,
+}
+
+impl Visibility {
+    pub fn parse(s: &str) -> Option<Visibility> {
+        match s {
+            "public" => Some(Visibility::Public),
+            "unlisted" => Some(Visibility::Unlisted),
+            "private" => Some(Visibility::Private),
+            _ => None,
+        }
+    }
+}
+
+/// Settings of a new room
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RoomOptions {
+    /// game type of the table, `None` for a room without one
+    pub game: Option<String>,
+    /// board size of the games, `None` for the default of the game type
+    pub size: Option<usize>,
+    /// maximum number of members, `None` for no limit
+    pub capacity: Option<usize>,
+    pub visibility: Visibility,
+    /// needed to join the room, also the password given to join an
+    /// existing room
+    pub password: Option<String>,
+}
+
+impl Default for RoomOptions {
+    fn default() -> Self {
+        RoomOptions {
+            game: Some("reversi".to_owned()),
+            size: None,
+            capacity: None,
+            visibility: Visibility::Public,
+            password: None,
+        }
+    }
 }
 
 /// Join room, if room does not exists create new one
-#[derive(Message)]
 pub struct Join {
     /// Client id
     pub id: usize,
     /// Room name
     pub name: String,
+    /// used if the room has to be created
+    pub options: RoomOptions,
+    /// wait for a free slot if the room is full
+    pub queue: bool,
+    /// token from `CreateInvite`, lets the session in without the password
+    pub invite: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JoinStatus {
+    Joined,
+    /// waiting for a free slot, 1 is the next to enter
+    Queued(usize),
+}
+
+impl actix::Message for Join {
+    type Result = Result<JoinStatus, String>;
 }
 
 struct User {
     pub name: RefCell<String>,
+    /// id other clients know the user by, the session id stays private
+    pub public_id: String,
     pub addr: Recipient<Message>,
+    pub joined: Recipient<Joined>,
+    pub peer: Option<IpAddr>,
+}
+
+/// A session banned from a room. There are no accounts, so the peer
+/// address is what outlives the session: a banned user who reconnects
+/// stays out, but so does everyone behind the same address, and a user
+/// who moves to another network gets back in.
+struct Ban {
+    id: usize,
+    /// name at the time of the ban, `Unban` goes by it
+    name: String,
+    peer: Option<IpAddr>,
+}
+
+impl Ban {
+    fn matches(&self, id: usize, peer: Option<IpAddr>) -> bool {
+        self.id == id || (self.peer.is_some() && self.peer == peer)
+    }
+}
+
+/// A message in the chat history of a room
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ChatMessage {
+    /// ids grow with every message on the server
+    pub id: u64,
+    pub sender: Member,
+    pub text: String,
+    /// seconds since the UNIX epoch
+    pub time: u64,
+}
+
+/// A message from one user to another
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PrivateChat {
+    pub from: Member,
+    pub to: Member,
+    pub text: String,
+    /// seconds since the UNIX epoch
+    pub time: u64,
+}
+
+/// A member that took another name
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Rename {
+    /// public id
+    pub id: String,
+    pub name: String,
+    pub old_name: String,
+}
+
+/// A user in a room as clients see it
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Member {
+    /// public id of the user, the same in every room while it is connected
+    pub id: String,
+    pub name: String,
+}
+
+/// The password of a `Join` after the `Hasher` has seen it
+enum Password {
+    /// no hashing was needed
+    None,
+    /// hash of the password of the room to create
+    Hashed(PasswordHash),
+    /// the room password the given one matched
+    Verified(PasswordHash),
+}
+
+/// Computer player seated in a room
+struct Bot {
+    room: String,
+    level: Level,
+}
+
+/// A room and everything kept for it, closing the room drops it all
+struct Room {
+    sessions: HashSet<usize>,
+    /// game table, `None` for a room without one
+    game: Option<Box<dyn Game>>,
+    /// clock of a timed game
+    clock: Option<Clock>,
+    /// board size set when the room was created
+    size: Option<usize>,
+    /// maximum number of members, `None` for no limit
+    capacity: Option<usize>,
+    /// sessions waiting for a free slot
+    queue: VecDeque<usize>,
+    visibility: Visibility,
+    password: Option<PasswordHash>,
+    /// the creator or first joiner, Main has none
+    owner: Option<usize>,
+    moderators: HashSet<usize>,
+    bans: Vec<Ban>,
+    /// the last `CHAT_HISTORY` messages
+    chat: VecDeque<ChatMessage>,
+    /// pending takeback request, the id of the requester
+    undo_request: Option<usize>,
+    /// pending draw offer, the id of the player who offered
+    draw_offer: Option<usize>,
+    /// default rooms are never removed
+    pinned: bool,
+    /// when the room became idle
+    idle_since: Option<Instant>,
 }
 
 pub struct Server {
     sessions: HashMap<usize, User>,
-    rooms: HashMap<String, HashSet<usize>>,
-    reversies: HashMap<String, Reversi>,
+    rooms: HashMap<String, Room>,
+    /// seated computer players, keyed by their player id
+    bots: HashMap<usize, Bot>,
+    /// runs the AI search, started with the actor
+    ai: Option<Addr<Ai>>,
+    /// hashes the room passwords, started with the actor
+    hasher: Option<Addr<Hasher>>,
+    /// id of the next chat message
+    next_chat_id: u64,
+    /// how long an idle room is kept
+    room_idle_timeout: Duration,
+    /// sessions that have listed the rooms
+    listeners: HashSet<usize>,
+    /// signs the invite tokens
+    secret: [u8; 32],
+    /// ids of the revoked and used up invites until they expire
+    revoked: HashMap<u64, u64>,
     rng: ThreadRng,
 }
 
 impl Default for Server {
     fn default() -> Server {
+        let mut server = Server {
+            sessions: HashMap::new(),
+            rooms: HashMap::new(),
+            bots: HashMap::new(),
+            ai: None,
+            hasher: None,
+            next_chat_id: 1,
+            room_idle_timeout: ROOM_IDLE_TIMEOUT,
+            listeners: HashSet::new(),
+            secret: rand::thread_rng().gen(),
+            revoked: HashMap::new(),
+            rng: rand::thread_rng(),
+        };
+
         // default room
-        let mut rooms = HashMap::new();
-        let mut reversies = HashMap::new();
-        rooms.insert("Main".to_owned(), HashSet::new());
+        let _ = server.create_room(
+            "Main",
+            &RoomOptions {
+                game: None,
+                ..RoomOptions::default()
+            },
+        );
         let default_rooms = ["room1", "room2", "room3", "room4", "room5"];
         for room in &default_rooms {
-            rooms.insert(room.to_string(), HashSet::new());
-            reversies.insert(room.to_string(), Reversi::new());
+            let _ = server.create_room(room, &RoomOptions::default());
         }
-
-        Server {
-            sessions: HashMap::new(),
-            rooms,
-            reversies,
-            rng: rand::thread_rng(),
+        for room in server.rooms.values_mut() {
+            room.pinned = true;
         }
+        server
     }
 }
 
 impl Server {
-    /// Send message to all users in the room
-    fn send_message(&self, room: &str, message: &str, skip_id: usize) {
-        if let Some(sessions) = self.rooms.get(room) {
-            for id in sessions {
+    /// Keep empty rooms for `timeout` before they are removed
+    pub fn with_room_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.room_idle_timeout = timeout;
+        self
+    }
+
+    /// Add a room, with a game table if `options` has a game type.
+    /// The password is hashed on the calling thread.
+    pub fn create_room(&mut self, name: &str, options: &RoomOptions) -> Result<(), String> {
+        let password = options.password.as_deref().map(PasswordHash::new);
+        self.add_room(name, options, password)
+    }
+
+    /// Add a room locked with the hash of `options.password`
+    fn add_room(
+        &mut self,
+        name: &str,
+        options: &RoomOptions,
+        password: Option<PasswordHash>,
+    ) -> Result<(), String> {
+        if self.rooms.contains_key(name) {
+            return Err("room already exists".to_owned());
+        }
+        if options.capacity == Some(0) {
+            return Err("capacity must be at least 1".to_owned());
+        }
+        if options.visibility == Visibility::Private && options.password.is_none() {
+            return Err("a private room needs a password".to_owned());
+        }
+        let game = match &options.game {
+            Some(kind) => {
+                let game =
+                    game::create(kind).ok_or_else(|| format!("unknown game type {:?}", kind))?;
+                if let Some(size) = options.size {
+                    // try the board settings on a game that is thrown away
+                    game::create(kind).unwrap().start(Some(size))?;
+                }
+                Some(game)
+            }
+            None if options.size.is_some() => {
+                return Err("a room without a game has no board".to_owned());
+            }
+            None => None,
+        };
+        let room = Room {
+            sessions: HashSet::new(),
+            game,
+            clock: None,
+            size: options.size,
+            capacity: options.capacity,
+            queue: VecDeque::new(),
+            visibility: options.visibility,
+            password,
+            owner: None,
+            moderators: HashSet::new(),
+            bans: Vec::new(),
+            chat: VecDeque::new(),
+            undo_request: None,
+            draw_offer: None,
+            pinned: false,
+            idle_since: None,
+        };
+        self.rooms.insert(name.to_owned(), room);
+        Ok(())
+    }
+
+    /// Game table of `room`
+    fn game(&self, room: &str) -> Option<&dyn Game> {
+        self.rooms.get(room).and_then(|r| r.game.as_deref())
+    }
+
+    /// Drop the pending takeback request and draw offer of `room`
+    fn clear_offers(&mut self, room: &str) {
+        if let Some(r) = self.rooms.get_mut(room) {
+            r.undo_request = None;
+            r.draw_offer = None;
+        }
+    }
+
+    /// Join room, if room does not exists create new one.
+    /// `password` is what the `Hasher` made of the password in `msg`.
+    fn join(&mut self, msg: Join, password: Password) -> Result<JoinStatus, String> {
+        let Join {
+            id,
+            name,
+            options,
+            queue,
+            invite,
+        } = msg;
+        let invite = match invite {
+            Some(token) => Some(self.check_invite(&token, &name)?),
+            None => None,
+        };
+        let peer = self.sessions.get(&id).and_then(|u| u.peer);
+        if self
+            .rooms
+            .get(&name)
+            .is_some_and(|r| r.bans.iter().any(|ban| ban.matches(id, peer)))
+        {
+            return Err("you are banned from this room".to_owned());
+        }
+        let created = !self.rooms.contains_key(&name);
+        if created {
+            let hash = match &password {
+                Password::Hashed(hash) => Some(hash.clone()),
+                // the room closed while the password was checked
+                _ if options.password.is_some() => {
+                    return Err("the room was closed, try again".to_owned())
+                }
+                _ => None,
+            };
+            self.add_room(&name, &options, hash)?;
+        }
+        let member = self.rooms[&name].sessions.contains(&id);
+        if !created && !member && invite.is_none() {
+            if let Some(hash) = &self.rooms[&name].password {
+                if !matches!(&password, Password::Verified(checked) if checked == hash) {
+                    return Err("wrong password".to_owned());
+                }
+            }
+        }
+        if !member && self.is_full(&name) && !queue {
+            return Err("room is full".to_owned());
+        }
+        if let Some(invite) = invite.filter(|i| i.single_use) {
+            self.revoked.insert(invite.id, invite.expires);
+        }
+        if !member && self.is_full(&name) {
+            for r in self.rooms.values_mut() {
+                r.queue.retain(|queued| *queued != id);
+            }
+            let queue = &mut self.rooms.get_mut(&name).unwrap().queue;
+            queue.push_back(id);
+            return Ok(JoinStatus::Queued(queue.len()));
+        }
+        self.enter(id, &name);
+        Ok(JoinStatus::Joined)
+    }
+
+    /// Hashing `join` needs before it lets `msg` in: the password of a
+    /// new room, or the one given for a locked room
+    fn password_work(&self, msg: &Join) -> Option<(String, Option<PasswordHash>)> {
+        let password = msg.options.password.clone()?;
+        match self.rooms.get(&msg.name) {
+            None => Some((password, None)),
+            Some(r) if r.sessions.contains(&msg.id) || msg.invite.is_some() => None,
+            Some(r) => Some((password, Some(r.password.clone()?))),
+        }
+    }
+
+    /// The invite `token` if it lets sessions into `room` now
+    fn check_invite(&self, token: &str, room: &str) -> Result<Invite, String> {
+        let invite = Invite::verify(token, &self.secret).ok_or("invalid invite")?;
+        if invite.room != room || !self.rooms.contains_key(room) {
+            return Err("the invite is for another room".to_owned());
+        }
+        if invite.expires <= unix_time() {
+            return Err("the invite has expired".to_owned());
+        }
+        if self.revoked.contains_key(&invite.id) {
+            return Err("the invite has been revoked or used".to_owned());
+        }
+        Ok(invite)
+    }
+
+    /// Rooms `ListRooms` shows
+    fn public_rooms(&self) -> Vec<String> {
+        let mut rooms: Vec<String> = self
+            .rooms
+            .iter()
+            .filter(|(name, r)| *name != "Main" && r.visibility == Visibility::Public)
+            .map(|(name, _)| name.clone())
+            .collect();
+        rooms.sort();
+        rooms
+    }
+
+    fn room_info(&self, name: &str) -> RoomInfo {
+        let room = &self.rooms[name];
+        let sessions = &room.sessions;
+        let game = room.game.as_deref();
+        let seat = |seat| match game.map(|g| g.player(seat)) {
+            Some(0) | None => None,
+            Some(id) => Some(self.get_user_name(id)),
+        };
+        let playing = sessions
+            .iter()
+            .filter(|id| game.is_some_and(|g| g.seat_of(**id).is_some()))
+            .count();
+        RoomInfo {
+            name: name.to_owned(),
+            members: sessions.len(),
+            capacity: room.capacity,
+            game: game.map(|g| g.kind()),
+            in_progress: game.is_some_and(|g| g.state() != States::End),
+            players: [seat(Player::One), seat(Player::Two)],
+            spectators: sessions.len() - playing,
+        }
+    }
+
+    fn list_rooms(&self, msg: &ListRooms) -> RoomList {
+        let rooms: Vec<RoomInfo> = self
+            .public_rooms()
+            .iter()
+            .map(|name| self.room_info(name))
+            .filter(|room| {
+                !msg.open_seats
+                    || (room.game.is_some()
+                        && room.players.contains(&None)
+                        && !self.is_full(&room.name))
+            })
+            .filter(|room| !msg.in_progress || room.in_progress)
+            .collect();
+        let limit = msg.limit.unwrap_or(MAX_LIST_LIMIT).min(MAX_LIST_LIMIT);
+        RoomList {
+            total: rooms.len(),
+            rooms: rooms.into_iter().skip(msg.offset).take(limit).collect(),
+            offset: msg.offset,
+        }
+    }
+
+    fn is_full(&self, room: &str) -> bool {
+        self.rooms
+            .get(room)
+            .is_some_and(|r| r.capacity.is_some_and(|c| r.sessions.len() >= c))
+    }
+
+    /// Take the session `id` out of its room and the waiting lists
+    fn leave(&mut self, id: usize) {
+        let member = self.member(id);
+        let mut rooms = Vec::new();
+
+        // remove session from all rooms
+        for (n, r) in &mut self.rooms {
+            r.queue.retain(|queued| *queued != id);
+            if r.sessions.remove(&id) {
+                rooms.push(n.to_owned());
+            }
+        }
+        let running: HashSet<String> = self
+            .rooms
+            .iter()
+            .filter(|(_, r)| r.game.as_ref().is_some_and(|g| g.state() != States::End))
+            .map(|(room, _)| room.clone())
+            .collect();
+        self.unregist_player(id);
+
+        // send message to other users
+        for room in rooms {
+            if self.rooms.get(&room).is_some_and(|r| r.owner == Some(id)) {
+                self.pass_ownership(&room);
+            }
+            self.release_bots(&room);
+            if let Some(member) = &member {
+                let event = Event::MemberLeft {
+                    room: room.clone(),
+                    data: member.clone(),
+                };
+                self.send_event(&room, &event, 0);
+            }
+            self.send_game_state(&room);
+            if running.contains(&room) {
+                self.send_game_over(&room);
+            }
+            self.admit(&room);
+        }
+    }
+
+    /// Move the session `id` into `room`
+    fn enter(&mut self, id: usize, room: &str) {
+        self.leave(id);
+        if let Some(r) = self.rooms.get_mut(room) {
+            r.sessions.insert(id);
+            if room != "Main" && r.owner.is_none() {
+                r.owner = Some(id);
+            }
+        }
+        self.announce(id, room);
+    }
+
+    /// Tell the others in `room` that the session `id` came in and send it
+    /// who is there
+    fn announce(&self, id: usize, room: &str) {
+        let member = match self.member(id) {
+            Some(member) => member,
+            None => return,
+        };
+        let event = Event::MemberJoined {
+            room: room.to_owned(),
+            data: member,
+        };
+        self.send_event(room, &event, id);
+        let members = Event::Members {
+            room: room.to_owned(),
+            data: self.members(room),
+        };
+        self.send_to(id, &members);
+        let chat = Event::ChatHistory {
+            room: room.to_owned(),
+            data: self.chat_history(room, None),
+        };
+        self.send_to(id, &chat);
+    }
+
+    /// Keep a chat message in the history of `room`, `None` if there is no
+    /// such room
+    fn record_chat(&mut self, room: &str, sender: Member, text: &str) -> Option<ChatMessage> {
+        let chat = &mut self.rooms.get_mut(room)?.chat;
+        let message = ChatMessage {
+            id: self.next_chat_id,
+            sender,
+            text: text.to_owned(),
+            time: unix_time(),
+        };
+        chat.push_back(message.clone());
+        if chat.len() > CHAT_HISTORY {
+            chat.pop_front();
+        }
+        self.next_chat_id += 1;
+        Some(message)
+    }
+
+    /// The last `CHAT_PAGE` messages in `room` before the message `before`,
+    /// or the latest ones, oldest first
+    fn chat_history(&self, room: &str, before: Option<u64>) -> Vec<ChatMessage> {
+        let chat = match self.rooms.get(room) {
+            Some(r) => &r.chat,
+            None => return Vec::new(),
+        };
+        let end = match before {
+            Some(before) => chat.partition_point(|m| m.id < before),
+            None => chat.len(),
+        };
+        chat.range(end.saturating_sub(CHAT_PAGE)..end)
+            .cloned()
+            .collect()
+    }
+
+    fn member(&self, id: usize) -> Option<Member> {
+        let user = self.sessions.get(&id)?;
+        Some(Member {
+            id: user.public_id.clone(),
+            name: user.name.borrow().clone(),
+        })
+    }
+
+    /// Users in `room`, sorted by name
+    fn members(&self, room: &str) -> Vec<Member> {
+        let mut members: Vec<Member> = self
+            .rooms
+            .get(room)
+            .into_iter()
+            .flat_map(|r| &r.sessions)
+            .filter_map(|id| self.member(*id))
+            .collect();
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+        members
+    }
+
+    /// The owner left `room`, a moderator or else any member takes over
+    fn pass_ownership(&mut self, room: &str) {
+        let room = match self.rooms.get_mut(room) {
+            Some(room) => room,
+            None => return,
+        };
+        room.owner = room
+            .sessions
+            .iter()
+            .find(|id| room.moderators.contains(id))
+            .or_else(|| room.sessions.iter().next())
+            .cloned();
+    }
+
+    fn is_moderator(&self, room: &str, id: usize) -> bool {
+        self.rooms
+            .get(room)
+            .is_some_and(|r| r.owner == Some(id) || r.moderators.contains(&id))
+    }
+
+    /// Member of `room` called `name`
+    fn find_member(&self, room: &str, name: &str) -> Result<usize, String> {
+        let ids: Vec<usize> = self
+            .rooms
+            .get(room)
+            .into_iter()
+            .flat_map(|r| &r.sessions)
+            .filter(|id| self.get_user_name(**id) == name)
+            .cloned()
+            .collect();
+        match ids[..] {
+            [id] => Ok(id),
+            [] => Err(format!("{} is not in the room", name)),
+            _ => Err(format!("more than one member is called {}", name)),
+        }
+    }
+
+    /// Session of the user with the public id `user`, or else the name.
+    /// A name shared by several users is refused, the error lists their
+    /// public ids to pick from.
+    fn find_user(&self, user: &str) -> Result<usize, String> {
+        if let Some((id, _)) = self.sessions.iter().find(|(_, u)| u.public_id == user) {
+            return Ok(*id);
+        }
+        let ids: Vec<usize> = self
+            .sessions
+            .iter()
+            .filter(|(_, u)| *u.name.borrow() == user)
+            .map(|(id, _)| *id)
+            .collect();
+        match ids[..] {
+            [id] => Ok(id),
+            [] => Err(format!("unknown user: {}", user)),
+            _ => {
+                let mut public_ids: Vec<&str> = ids
+                    .iter()
+                    .map(|id| self.sessions[id].public_id.as_str())
+                    .collect();
+                public_ids.sort_unstable();
+                Err(format!(
+                    "more than one user is called {}, send to one of their ids: {}",
+                    user,
+                    public_ids.join(", ")
+                ))
+            }
+        }
+    }
+
+    /// Send `target` from `room` back to Main, also banning it if `ban`.
+    /// Only the owner and moderators can do it, to members below them.
+    fn kick(&mut self, room: &str, id: usize, target: usize, ban: bool) -> Result<(), String> {
+        if room == "Main" {
+            return Err("no one can be kicked from Main".to_owned());
+        }
+        if !self.is_moderator(room, id) {
+            return Err("you are not a moderator".to_owned());
+        }
+        let owner = self.rooms.get(room).and_then(|r| r.owner);
+        if target == id
+            || owner == Some(target)
+            || (owner != Some(id) && self.is_moderator(room, target))
+        {
+            return Err("you can't kick this member".to_owned());
+        }
+        if !self
+            .rooms
+            .get(room)
+            .is_some_and(|r| r.sessions.contains(&target))
+        {
+            return Err("not in the room".to_owned());
+        }
+        let name = self.get_user_name(target);
+        let peer = self.sessions.get(&target).and_then(|u| u.peer);
+        if let Some(r) = self.rooms.get_mut(room) {
+            if ban {
+                r.bans.push(Ban {
+                    id: target,
+                    name,
+                    peer,
+                });
+            }
+            r.moderators.remove(&target);
+        }
+        let data = room.to_owned();
+        self.send_to(
+            target,
+            &if ban {
+                Event::Banned { data }
+            } else {
+                Event::Kicked { data }
+            },
+        );
+        if let Some(user) = self.sessions.get(&target) {
+            let _ = user.joined.do_send(Joined {
+                room: "Main".to_owned(),
+            });
+        }
+        // leaving frees the seat of a player
+        self.enter(target, "Main");
+        Ok(())
+    }
+
+    /// Let the sessions waiting for `room` in while there is space
+    fn admit(&mut self, room: &str) {
+        while !self.is_full(room) {
+            let id = match self.rooms.get_mut(room).and_then(|r| r.queue.pop_front()) {
+                Some(id) => id,
+                None => break,
+            };
+            if let Some(user) = self.sessions.get(&id) {
+                let _ = user.joined.do_send(Joined {
+                    room: room.to_owned(),
+                });
+                self.enter(id, room);
+            }
+        }
+    }
+
+    /// Send `event` to all users in the room but `skip_id`
+    fn send_event(&self, room: &str, event: &Event, skip_id: usize) {
+        let message = event.to_json();
+        if let Some(r) = self.rooms.get(room) {
+            for id in &r.sessions {
                 if *id != skip_id {
                     if let Some(user) = self.sessions.get(id) {
-                        let _ = user.addr.do_send(Message(message.to_owned()));
+                        let _ = user.addr.do_send(Message(message.clone()));
                     }
                 }
             }
         }
     }
 
+    /// Send `event` to the session `id` alone
+    fn send_to(&self, id: usize, event: &Event) {
+        if let Some(user) = self.sessions.get(&id) {
+            let _ = user.addr.do_send(Message(event.to_json()));
+        }
+    }
+
     fn get_user_name(&self, id: usize) -> String {
         if let Some(user) = self.sessions.get(&id) {
             return user.name.borrow_mut().clone();
         }
+        if let Some(bot) = self.bots.get(&id) {
+            return format!("Bot ({})", bot.level.name());
+        }
         "".to_owned()
     }
 
-    fn unregist_reversi_player(&self, id: usize) {
-        for (_, reversi) in self.reversies.iter() {
-            if reversi.player1_id.get() == id {
-                reversi.player1_id.set(0);
-                reversi.end();
+    fn unregist_player(&self, id: usize) {
+        for game in self.rooms.values().filter_map(|r| r.game.as_ref()) {
+            if let Some(seat) = game.seat_of(id) {
+                game.abandon(id);
+                game.set_player(seat, 0);
             }
-            if reversi.player2_id.get() == id {
-                reversi.player2_id.set(0);
-                reversi.end();
+        }
+    }
+
+    /// Free the seats of the bots in `room` once no user is left there
+    fn release_bots(&mut self, room: &str) {
+        if self.rooms.get(room).is_some_and(|r| !r.sessions.is_empty()) {
+            return;
+        }
+        let ids: Vec<usize> = self
+            .bots
+            .iter()
+            .filter(|(_, bot)| bot.room == room)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.bots.remove(&id);
+            self.unregist_player(id);
+        }
+    }
+
+    /// Let the AI think if a bot has the turn in `room`.
+    /// The move comes back through `PutDisc` like a human player's.
+    fn play_bot(&self, room: &str, ctx: &mut Context<Self>) {
+        let reversi = self
+            .game(room)
+            .and_then(|g| g.as_any().downcast_ref::<Reversi>());
+        let (reversi, ai) = match (reversi, &self.ai) {
+            (Some(reversi), Some(ai)) => (reversi, ai),
+            _ => return,
+        };
+        let (id, player1) = match *reversi.state.borrow() {
+            States::TurnPlayer1 => (reversi.player1_id.get(), true),
+            States::TurnPlayer2 => (reversi.player2_id.get(), false),
+            States::End => return,
+        };
+        let level = match self.bots.get(&id) {
+            Some(bot) => bot.level,
+            None => return,
+        };
+        let board = reversi.board.get();
+        let room = room.to_owned();
+        ctx.spawn(
+            ai.send(Think {
+                board,
+                player1,
+                level,
+            })
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                // drop the move if the game went on without the bot
+                let current = act
+                    .game(&room)
+                    .and_then(|g| g.as_any().downcast_ref::<Reversi>())
+                    .map(|r| r.board.get());
+                if let (Ok(Some((x, y))), Some(current)) = (res, current) {
+                    if current == board {
+                        ctx.notify(PutDisc { room, id, x, y });
+                    }
+                }
+                fut::ok(())
+            }),
+        );
+    }
+
+    /// Run the clock of the player to move in `room`, stop it once the game is over
+    fn update_clock(&mut self, room: &str) {
+        let r = match self.rooms.get_mut(room) {
+            Some(r) => r,
+            None => return,
+        };
+        if let (Some(clock), Some(game)) = (&mut r.clock, &r.game) {
+            let player = match game.state() {
+                States::TurnPlayer1 => Some(0),
+                States::TurnPlayer2 => Some(1),
+                States::End => None,
+            };
+            clock.switch(player, Instant::now());
+        }
+    }
+
+    /// Flag the players whose time has run out
+    fn check_clocks(&mut self) {
+        let now = Instant::now();
+        let mut flagged = Vec::new();
+        for (room, r) in self.rooms.iter_mut() {
+            let (clock, game) = match (&mut r.clock, &r.game) {
+                (Some(clock), Some(game)) => (clock, game),
+                _ => continue,
+            };
+            if game.state() == States::End {
+                // ended without a move, e.g. a player left
+                clock.switch(None, now);
+                continue;
             }
+            let id = match clock.flagged(now) {
+                Some(0) => game.player(Player::One),
+                Some(_) => game.player(Player::Two),
+                None => continue,
+            };
+            game.time_out(id);
+            clock.switch(None, now);
+            flagged.push(room.clone());
+        }
+        for room in flagged {
+            self.clear_offers(&room);
+            self.send_game_state(&room);
+            self.send_game_over(&room);
         }
     }
 
-    fn send_reversi_state(&self, room: &str) {
-        if let Some(reversi) = self.reversies.get(room) {
-            self.send_message(
-                room,
-                &json!({
-                    "cmd": "update_state",
-                    "state": reversi.state.clone(),
-                    "data": reversi.get_discs(),
-                })
-                .to_string(),
-                0,
-            );
+    /// Remove the dynamic rooms that have been empty, without a game in
+    /// progress, for `room_idle_timeout`
+    fn reap_rooms(&mut self) {
+        let time = unix_time();
+        self.revoked.retain(|_, expires| *expires > time);
+        let now = Instant::now();
+        let mut closed = Vec::new();
+        for (name, room) in &mut self.rooms {
+            let playing = room.game.as_ref().is_some_and(|g| g.state() != States::End);
+            if room.pinned || !room.sessions.is_empty() || playing {
+                room.idle_since = None;
+                continue;
+            }
+            let since = *room.idle_since.get_or_insert(now);
+            if now.duration_since(since) >= self.room_idle_timeout {
+                closed.push(name.clone());
+            }
+        }
+        for name in closed {
+            self.rooms.remove(&name);
+            let event = Event::RoomClosed { data: name };
+            for id in &self.listeners {
+                self.send_to(*id, &event);
+            }
+        }
+    }
+
+    fn send_game_state(&self, room: &str) {
+        if let Some(Room {
+            game: Some(game),
+            clock,
+            ..
+        }) = self.rooms.get(room)
+        {
+            let event = Event::UpdateState {
+                game: game.kind(),
+                clock: clock.as_ref().map(|c| c.state(Instant::now())),
+                state: game.to_json(),
+            };
+            self.send_event(room, &event, 0);
+        }
+    }
+
+    /// Tell `room` how its game ended, once right after it ends
+    fn send_game_over(&self, room: &str) {
+        if let Some(data) = self.game(room).and_then(|g| g.result()) {
+            self.send_event(room, &Event::GameOver { data }, 0);
         }
     }
 }
@@ -145,6 +1074,13 @@ impl Actor for Server {
     /// We are going to use simple Context, we just need ability to communicate
     /// with other actors.
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        self.ai = Some(SyncArbiter::start(2, || Ai));
+        self.hasher = Some(SyncArbiter::start(2, || Hasher));
+        ctx.run_interval(CLOCK_TICK, |act, _| act.check_clocks());
+        ctx.run_interval(REAP_INTERVAL, |act, _| act.reap_rooms());
+    }
 }
 
 /// Handler for Connect message.
@@ -156,21 +1092,22 @@ impl Handler<Connect> for Server {
     fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
         println!("Someone joined");
 
-        // notify all users in same room
-        self.send_message(&"Main".to_owned(), "Someone joined", 0);
-
         // register session with random id
         let id = self.rng.gen::<usize>();
         self.sessions.insert(
             id,
             User {
                 name: RefCell::new("名無し".to_owned()),
+                public_id: format!("{:016x}", self.rng.gen::<u64>()),
                 addr: msg.addr,
+                joined: msg.joined,
+                peer: msg.peer,
             },
         );
 
-        // auto join session to Main room
-        self.rooms.get_mut(&"Main".to_owned()).unwrap().insert(id);
+        // auto join session to Main room and notify all users there
+        self.rooms.get_mut("Main").unwrap().sessions.insert(id);
+        self.announce(id, "Main");
 
         // send id back
         id
@@ -184,24 +1121,11 @@ impl Handler<Disconnect> for Server {
     fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
         println!("Someone disconnected");
 
-        let mut rooms: Vec<String> = Vec::new();
+        self.listeners.remove(&msg.id);
 
-        // remove address
-        if self.sessions.remove(&msg.id).is_some() {
-            // remove session from all rooms
-            for (name, sessions) in &mut self.rooms {
-                if sessions.remove(&msg.id) {
-                    rooms.push(name.to_owned());
-                }
-            }
-        }
-        self.unregist_reversi_player(msg.id);
-
-        // send message to other users
-        for room in rooms {
-            self.send_message(&room, "Someone disconnected", 0);
-            self.send_reversi_state(&room)
-        }
+        // the others see who left before the address is removed
+        self.leave(msg.id);
+        self.sessions.remove(&msg.id);
     }
 }
 
@@ -210,7 +1134,76 @@ impl Handler<ClientMessage> for Server {
     type Result = ();
 
     fn handle(&mut self, msg: ClientMessage, _: &mut Context<Self>) {
-        self.send_message(&msg.room, msg.msg.as_str(), msg.id);
+        let member = match self.member(msg.id) {
+            Some(member) => member,
+            None => return,
+        };
+        if let Some(message) = self.record_chat(&msg.room, member, &msg.msg) {
+            let event = Event::Chat {
+                room: msg.room.clone(),
+                data: message,
+            };
+            self.send_event(&msg.room, &event, 0);
+        }
+    }
+}
+
+/// Send `text` to the user `to` alone, wherever it is. The sender gets a
+/// copy to show in its own chat.
+pub struct PrivateMessage {
+    /// Client id of the sender
+    pub id: usize,
+    /// public id or name of the receiver, see `Server::find_user`
+    pub to: String,
+    pub text: String,
+}
+impl actix::Message for PrivateMessage {
+    type Result = Result<String, String>;
+}
+impl Handler<PrivateMessage> for Server {
+    type Result = MessageResult<PrivateMessage>;
+
+    fn handle(&mut self, msg: PrivateMessage, _: &mut Context<Self>) -> Self::Result {
+        let from = match self.member(msg.id) {
+            Some(member) => member,
+            None => return MessageResult(Err("not connected".to_owned())),
+        };
+        let (to, receiver) = match self.find_user(&msg.to).map(|id| (id, self.member(id))) {
+            Ok((id, Some(member))) => (id, member),
+            Ok((_, None)) => return MessageResult(Err(format!("unknown user: {}", msg.to))),
+            Err(e) => return MessageResult(Err(e)),
+        };
+        let event = Event::PrivateMessage {
+            data: PrivateChat {
+                from,
+                to: receiver,
+                text: msg.text,
+                time: unix_time(),
+            },
+        };
+        self.send_to(to, &event);
+        if to != msg.id {
+            self.send_to(msg.id, &event);
+        }
+        MessageResult(Ok("delivered".to_owned()))
+    }
+}
+
+/// Chat messages of a room, `CHAT_PAGE` at most
+pub struct GetChat {
+    pub room: String,
+    /// id of a message, only older messages are returned.
+    /// `None` for the latest ones.
+    pub before: Option<u64>,
+}
+impl actix::Message for GetChat {
+    type Result = Vec<ChatMessage>;
+}
+impl Handler<GetChat> for Server {
+    type Result = MessageResult<GetChat>;
+
+    fn handle(&mut self, msg: GetChat, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.chat_history(&msg.room, msg.before))
     }
 }
 
@@ -218,49 +1211,49 @@ impl Handler<ClientMessage> for Server {
 impl Handler<ListRooms> for Server {
     type Result = MessageResult<ListRooms>;
 
-    fn handle(&mut self, _: ListRooms, _: &mut Context<Self>) -> Self::Result {
-        let mut rooms = Vec::new();
-
-        for key in self.rooms.keys() {
-            if key == "Main" {
-                continue;
-            }
-            rooms.push(key.to_owned())
-        }
-
-        rooms.sort();
-        MessageResult(rooms)
+    fn handle(&mut self, msg: ListRooms, _: &mut Context<Self>) -> Self::Result {
+        self.listeners.insert(msg.id);
+        MessageResult(self.list_rooms(&msg))
     }
 }
 
 /// Join room, send disconnect message to old room
 /// send join message to new room
+///
+/// Passwords are hashed on the `Hasher`, the join goes on when it answers.
 impl Handler<Join> for Server {
-    type Result = ();
+    type Result = ResponseActFuture<Self, JoinStatus, String>;
 
-    fn handle(&mut self, msg: Join, _: &mut Context<Self>) {
-        let Join { id, name } = msg;
-        let mut rooms = Vec::new();
-
-        // remove session from all rooms
-        for (n, sessions) in &mut self.rooms {
-            if sessions.remove(&id) {
-                rooms.push(n.to_owned());
-            }
-        }
-        self.unregist_reversi_player(id);
-
-        // send message to other users
-        for room in rooms {
-            self.send_message(&room, "Someone disconnected", 0);
-            self.send_reversi_state(&room)
+    fn handle(&mut self, msg: Join, _: &mut Context<Self>) -> Self::Result {
+        let (hasher, (password, hash)) = match (&self.hasher, self.password_work(&msg)) {
+            (Some(hasher), Some(work)) => (hasher, work),
+            _ => return Box::new(fut::result(self.join(msg, Password::None))),
+        };
+        match hash {
+            Some(hash) => Box::new(
+                hasher
+                    .send(Verify {
+                        hash: hash.clone(),
+                        password,
+                    })
+                    .into_actor(self)
+                    .then(move |res, act, _| {
+                        fut::result(match res {
+                            Ok(true) => act.join(msg, Password::Verified(hash)),
+                            Ok(false) => Err("wrong password".to_owned()),
+                            Err(_) => Err("the password could not be checked".to_owned()),
+                        })
+                    }),
+            ),
+            None => Box::new(hasher.send(Hash(password)).into_actor(self).then(
+                move |res, act, _| {
+                    fut::result(match res {
+                        Ok(hash) => act.join(msg, Password::Hashed(hash)),
+                        Err(_) => Err("the password could not be checked".to_owned()),
+                    })
+                },
+            )),
         }
-
-        if self.rooms.get_mut(&name).is_none() {
-            self.rooms.insert(name.clone(), HashSet::new());
-        }
-        self.send_message(&name, "Someone connected", id);
-        self.rooms.get_mut(&name).unwrap().insert(id);
     }
 }
 
@@ -274,37 +1267,50 @@ impl Handler<SetName> for Server {
 
     fn handle(&mut self, msg: SetName, _: &mut Context<Self>) -> Self::Result {
         let SetName { id, new_name } = msg;
-        if let Some(user) = self.sessions.get(&id) {
-            user.name.replace(new_name);
+        let old_name = match self.sessions.get(&id) {
+            Some(user) => user.name.replace(new_name.clone()),
+            None => return,
+        };
+        if old_name == new_name {
+            return;
+        }
+        let data = Rename {
+            id: self.sessions[&id].public_id.clone(),
+            name: new_name,
+            old_name,
+        };
+        for (room, r) in &self.rooms {
+            if r.sessions.contains(&id) {
+                let event = Event::MemberRenamed {
+                    room: room.clone(),
+                    data: data.clone(),
+                };
+                self.send_event(room, &event, 0);
+            }
         }
     }
 }
 
-pub struct GetMemberNames {
+pub struct GetMembers {
     pub room: String,
 }
-impl actix::Message for GetMemberNames {
-    type Result = Vec<String>;
+impl actix::Message for GetMembers {
+    type Result = Vec<Member>;
 }
-impl Handler<GetMemberNames> for Server {
-    type Result = MessageResult<GetMemberNames>;
+impl Handler<GetMembers> for Server {
+    type Result = MessageResult<GetMembers>;
 
-    fn handle(&mut self, msg: GetMemberNames, _: &mut Context<Self>) -> Self::Result {
-        let mut ret: Vec<String> = Vec::new();
-        if let Some(sessions) = self.rooms.get(&msg.room) {
-            for id in sessions {
-                if let Some(user) = self.sessions.get(id) {
-                    ret.push(user.name.borrow_mut().clone());
-                }
-            }
-        }
-        ret.sort();
-        MessageResult(ret)
+    fn handle(&mut self, msg: GetMembers, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.members(&msg.room))
     }
 }
 
 pub struct Start {
     pub room: String,
+    /// board size of the new game, `None` for the default of the game type
+    pub size: Option<usize>,
+    /// play with clocks
+    pub time: Option<TimeControl>,
 }
 impl actix::Message for Start {
     type Result = Result<String, String>;
@@ -312,30 +1318,34 @@ impl actix::Message for Start {
 impl Handler<Start> for Server {
     type Result = MessageResult<Start>;
 
-    fn handle(&mut self, msg: Start, _: &mut Context<Self>) -> Self::Result {
-        if let Some(sessions) = self.rooms.get(&msg.room) {
-            if sessions.len() < 2 {
+    fn handle(&mut self, msg: Start, ctx: &mut Context<Self>) -> Self::Result {
+        let bots = self.bots.values().filter(|b| b.room == msg.room).count();
+        if let Some(r) = self.rooms.get(&msg.room) {
+            if r.sessions.len() + bots < 2 {
                 return MessageResult(Err("not enough members".to_owned()));
             }
         }
-        if let Some(reversi) = self.reversies.get(&msg.room) {
-            if reversi.player1_id.get() == 0 || reversi.player2_id.get() == 0 {
+        if let Some(game) = self.game(&msg.room) {
+            if game.player(Player::One) == 0 || game.player(Player::Two) == 0 {
                 return MessageResult(Err("please regeist player1 and player2".to_owned()));
             }
-            reversi.init();
-            self.send_reversi_state(&msg.room);
+            let size = msg.size.or(self.rooms[&msg.room].size);
+            if let Err(e) = game.start(size) {
+                return MessageResult(Err(e));
+            }
+            self.clear_offers(&msg.room);
+            if let Some(r) = self.rooms.get_mut(&msg.room) {
+                r.clock = msg.time.map(Clock::new);
+            }
+            self.update_clock(&msg.room);
+            self.send_game_state(&msg.room);
+            self.play_bot(&msg.room, ctx);
             return MessageResult(Ok("success".to_owned()));
         }
         MessageResult(Err("something wrong".to_owned()))
     }
 }
 
-#[derive(Serialize, Clone)]
-pub enum Player {
-    One = 1,
-    Two = 2,
-}
-
 #[derive(Message)]
 #[rtype(String)]
 pub struct GetPlayer {
@@ -346,11 +1356,8 @@ impl Handler<GetPlayer> for Server {
     type Result = String;
 
     fn handle(&mut self, msg: GetPlayer, _: &mut Context<Self>) -> Self::Result {
-        if let Some(reversi) = self.reversies.get(&msg.room) {
-            return match msg.player {
-                Player::One => self.get_user_name(reversi.player1_id.get()),
-                Player::Two => self.get_user_name(reversi.player2_id.get()),
-            };
+        if let Some(game) = self.game(&msg.room) {
+            return self.get_user_name(game.player(msg.player));
         }
         "".to_owned()
     }
@@ -362,55 +1369,55 @@ pub struct RegistPlayer {
     pub room: String,
     pub id: usize,
     pub player: Player,
+    /// seat a computer player of this level instead of the session `id`
+    pub bot: Option<Level>,
 }
 impl Handler<RegistPlayer> for Server {
     type Result = bool;
 
-    fn handle(&mut self, msg: RegistPlayer, _: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, mut msg: RegistPlayer, ctx: &mut Context<Self>) -> Self::Result {
+        if let Some(level) = msg.bot {
+            // only Reversi has an AI
+            let reversi = self
+                .game(&msg.room)
+                .is_some_and(|g| g.as_any().is::<Reversi>());
+            if !reversi {
+                return false;
+            }
+            msg.id = self.rng.gen::<usize>();
+            self.bots.insert(
+                msg.id,
+                Bot {
+                    room: msg.room.clone(),
+                    level,
+                },
+            );
+        }
         let mut result = false;
-        if let Some(reversi) = self.reversies.get(&msg.room) {
-            result = match msg.player {
-                Player::One => {
-                    if reversi.player1_id.get() == 0 && msg.id != reversi.player2_id.get() {
-                        reversi.player1_id.set(msg.id);
-                        self.send_message(
-                            &msg.room,
-                            &json!({
-                                "cmd": "registered_player1",
-                                "data": self.get_user_name(msg.id),
-                            })
-                            .to_string(),
-                            0,
-                        );
-                        true
-                    } else {
-                        false
-                    }
-                }
-                Player::Two => {
-                    if reversi.player2_id.get() == 0 && msg.id != reversi.player1_id.get() {
-                        reversi.player2_id.set(msg.id);
-                        self.send_message(
-                            &msg.room,
-                            &json!({
-                                "cmd": "registered_player2",
-                                "data": self.get_user_name(msg.id),
-                            })
-                            .to_string(),
-                            0,
-                        );
-                        true
-                    } else {
-                        false
-                    }
-                }
+        if let Some(game) = self.game(&msg.room) {
+            let seat = msg.player;
+            if game.player(seat) == 0 && msg.id != game.player(seat.other()) {
+                game.set_player(seat, msg.id);
+                let data = self.get_user_name(msg.id);
+                let event = match seat {
+                    Player::One => Event::RegisteredPlayer1 { data },
+                    Player::Two => Event::RegisteredPlayer2 { data },
+                };
+                self.send_event(&msg.room, &event, 0);
+                result = true;
             }
         }
+        if result {
+            self.play_bot(&msg.room, ctx);
+        } else {
+            self.bots.remove(&msg.id);
+        }
         result
     }
 }
 
 #[derive(Message)]
+#[rtype(result = "Result<(), SetDiscError>")]
 pub struct PutDisc {
     pub room: String,
     pub id: usize,
@@ -418,17 +1425,1105 @@ pub struct PutDisc {
     pub y: usize,
 }
 impl Handler<PutDisc> for Server {
-    type Result = ();
+    type Result = Result<(), SetDiscError>;
 
-    fn handle(&mut self, msg: PutDisc, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: PutDisc, ctx: &mut Context<Self>) -> Self::Result {
         let PutDisc { room, id, x, y } = msg;
-        if let Some(reversi) = self.reversies.get(&room) {
-            match reversi.set_disc(id, x, y) {
-                Ok(_) => {
-                    self.send_reversi_state(&room);
+        match self.game(&room) {
+            Some(game) => {
+                game.play(id, x, y)?;
+                self.clear_offers(&room);
+                self.update_clock(&room);
+                self.send_game_state(&room);
+                self.send_game_over(&room);
+                self.play_bot(&room, ctx);
+                Ok(())
+            }
+            None => Err(SetDiscError::NoGame),
+        }
+    }
+}
+
+pub struct GetLegalMoves {
+    pub room: String,
+}
+impl actix::Message for GetLegalMoves {
+    type Result = Vec<LegalMove>;
+}
+impl Handler<GetLegalMoves> for Server {
+    type Result = MessageResult<GetLegalMoves>;
+
+    fn handle(&mut self, msg: GetLegalMoves, _: &mut Context<Self>) -> Self::Result {
+        let moves = self
+            .game(&msg.room)
+            .and_then(|g| g.as_any().downcast_ref::<Reversi>())
+            .map(Reversi::legal_moves);
+        MessageResult(moves.unwrap_or_default())
+    }
+}
+
+pub struct GetHistory {
+    pub room: String,
+}
+impl actix::Message for GetHistory {
+    type Result = Option<History>;
+}
+impl Handler<GetHistory> for Server {
+    type Result = MessageResult<GetHistory>;
+
+    fn handle(&mut self, msg: GetHistory, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(
+            self.game(&msg.room)
+                .and_then(|g| g.as_any().downcast_ref::<Reversi>())
+                .map(Reversi::history),
+        )
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UndoAction {
+    /// ask the opponent to take back the last move
+    Request,
+    Accept,
+    Reject,
+}
+
+/// Takeback of one full move with the consent of the opponent
+pub struct Undo {
+    pub room: String,
+    pub id: usize,
+    pub action: UndoAction,
+}
+impl actix::Message for Undo {
+    type Result = Result<String, String>;
+}
+impl Handler<Undo> for Server {
+    type Result = MessageResult<Undo>;
+
+    fn handle(&mut self, msg: Undo, ctx: &mut Context<Self>) -> Self::Result {
+        let Undo { room, id, action } = msg;
+        let (game, undo_request) = match self.rooms.get(&room) {
+            Some(Room {
+                game: Some(game),
+                undo_request,
+                ..
+            }) => (game, *undo_request),
+            _ => return MessageResult(Err("no game in this room".to_owned())),
+        };
+        let opponent = match game.seat_of(id) {
+            Some(seat) => game.player(seat.other()),
+            None => return MessageResult(Err("you are not a player".to_owned())),
+        };
+        if game.state() == States::End {
+            return MessageResult(Err("the game is not running".to_owned()));
+        }
+        let requester = match action {
+            UndoAction::Request => {
+                if undo_request.is_some() {
+                    return MessageResult(Err("undo is already requested".to_owned()));
+                }
+                if !game.can_undo(id) {
+                    return MessageResult(Err("no move to undo".to_owned()));
                 }
-                Err(_) => {}
+                if !self.bots.contains_key(&opponent) {
+                    if let Some(r) = self.rooms.get_mut(&room) {
+                        r.undo_request = Some(id);
+                    }
+                    let data = self.get_user_name(id);
+                    self.send_event(&room, &Event::UndoRequested { data }, 0);
+                    return MessageResult(Ok("requested".to_owned()));
+                }
+                // bots always agree
+                id
+            }
+            UndoAction::Accept | UndoAction::Reject => match undo_request {
+                Some(requester) if requester == opponent => requester,
+                _ => return MessageResult(Err("no undo request to answer".to_owned())),
+            },
+        };
+        if action != UndoAction::Reject {
+            game.undo(requester);
+        }
+        if let Some(r) = self.rooms.get_mut(&room) {
+            r.undo_request = None;
+        }
+        if action == UndoAction::Reject {
+            self.send_event(&room, &Event::UndoRejected, 0);
+            return MessageResult(Ok("rejected".to_owned()));
+        }
+        self.update_clock(&room);
+        self.send_event(&room, &Event::UndoAccepted, 0);
+        self.send_game_state(&room);
+        self.play_bot(&room, ctx);
+        MessageResult(Ok("accepted".to_owned()))
+    }
+}
+
+/// Give up the game
+pub struct Resign {
+    pub room: String,
+    pub id: usize,
+}
+impl actix::Message for Resign {
+    type Result = Result<String, String>;
+}
+impl Handler<Resign> for Server {
+    type Result = MessageResult<Resign>;
+
+    fn handle(&mut self, msg: Resign, _: &mut Context<Self>) -> Self::Result {
+        let resigned = self.game(&msg.room).is_some_and(|game| game.resign(msg.id));
+        if !resigned {
+            return MessageResult(Err("you are not playing a game".to_owned()));
+        }
+        self.clear_offers(&msg.room);
+        self.update_clock(&msg.room);
+        self.send_game_state(&msg.room);
+        self.send_game_over(&msg.room);
+        MessageResult(Ok("resigned".to_owned()))
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawAction {
+    Offer,
+    Accept,
+}
+
+/// End the game as a draw when both players agree
+pub struct Draw {
+    pub room: String,
+    pub id: usize,
+    pub action: DrawAction,
+}
+impl actix::Message for Draw {
+    type Result = Result<String, String>;
+}
+impl Handler<Draw> for Server {
+    type Result = MessageResult<Draw>;
+
+    fn handle(&mut self, msg: Draw, _: &mut Context<Self>) -> Self::Result {
+        let Draw { room, id, action } = msg;
+        let (game, draw_offer) = match self.rooms.get(&room) {
+            Some(Room {
+                game: Some(game),
+                draw_offer,
+                ..
+            }) if game.state() != States::End => (game, *draw_offer),
+            _ => return MessageResult(Err("the game is not running".to_owned())),
+        };
+        let opponent = match game.seat_of(id) {
+            Some(seat) => game.player(seat.other()),
+            None => return MessageResult(Err("you are not a player".to_owned())),
+        };
+        match action {
+            DrawAction::Offer => {
+                if draw_offer == Some(id) {
+                    return MessageResult(Err("draw is already offered".to_owned()));
+                }
+                if let Some(r) = self.rooms.get_mut(&room) {
+                    r.draw_offer = Some(id);
+                }
+                let data = self.get_user_name(id);
+                self.send_event(&room, &Event::DrawOffered { data }, 0);
+                MessageResult(Ok("offered".to_owned()))
+            }
+            DrawAction::Accept => {
+                if draw_offer != Some(opponent) {
+                    return MessageResult(Err("no draw offer to accept".to_owned()));
+                }
+                game.agree_draw();
+                self.clear_offers(&room);
+                self.update_clock(&room);
+                self.send_game_state(&room);
+                self.send_game_over(&room);
+                MessageResult(Ok("accepted".to_owned()))
+            }
+        }
+    }
+}
+
+/// Mint an invite token for `room`
+pub struct CreateInvite {
+    pub room: String,
+    /// Client id, has to be in the room
+    pub id: usize,
+    pub single_use: bool,
+    /// how long the token is valid, at most `MAX_INVITE_TTL`
+    pub ttl: Duration,
+}
+impl actix::Message for CreateInvite {
+    type Result = Result<String, String>;
+}
+impl Handler<CreateInvite> for Server {
+    type Result = MessageResult<CreateInvite>;
+
+    fn handle(&mut self, msg: CreateInvite, _: &mut Context<Self>) -> Self::Result {
+        if !self
+            .rooms
+            .get(&msg.room)
+            .is_some_and(|r| r.sessions.contains(&msg.id))
+        {
+            return MessageResult(Err("you are not in the room".to_owned()));
+        }
+        if msg.ttl > MAX_INVITE_TTL {
+            return MessageResult(Err(format!(
+                "ttl must be at most {} seconds",
+                MAX_INVITE_TTL.as_secs()
+            )));
+        }
+        let invite = Invite {
+            id: self.rng.gen(),
+            room: msg.room,
+            expires: unix_time() + msg.ttl.as_secs(),
+            single_use: msg.single_use,
+        };
+        MessageResult(Ok(invite.sign(&self.secret)))
+    }
+}
+
+/// Make an invite token of `room` useless
+pub struct RevokeInvite {
+    pub room: String,
+    /// Client id, has to be in the room
+    pub id: usize,
+    pub token: String,
+}
+impl actix::Message for RevokeInvite {
+    type Result = Result<String, String>;
+}
+impl Handler<RevokeInvite> for Server {
+    type Result = MessageResult<RevokeInvite>;
+
+    fn handle(&mut self, msg: RevokeInvite, _: &mut Context<Self>) -> Self::Result {
+        if !self
+            .rooms
+            .get(&msg.room)
+            .is_some_and(|r| r.sessions.contains(&msg.id))
+        {
+            return MessageResult(Err("you are not in the room".to_owned()));
+        }
+        match Invite::verify(&msg.token, &self.secret) {
+            Some(invite) if invite.room == msg.room => {
+                self.revoked.insert(invite.id, invite.expires);
+                MessageResult(Ok("revoked".to_owned()))
+            }
+            _ => MessageResult(Err("invalid invite".to_owned())),
+        }
+    }
+}
+
+/// Send the member called `target` back to Main, and keep it out if `ban`
+pub struct Kick {
+    pub room: String,
+    /// Client id of the owner or a moderator
+    pub id: usize,
+    pub target: String,
+    pub ban: bool,
+}
+impl actix::Message for Kick {
+    type Result = Result<String, String>;
+}
+impl Handler<Kick> for Server {
+    type Result = MessageResult<Kick>;
+
+    fn handle(&mut self, msg: Kick, _: &mut Context<Self>) -> Self::Result {
+        let r = self
+            .find_member(&msg.room, &msg.target)
+            .and_then(|target| self.kick(&msg.room, msg.id, target, msg.ban));
+        MessageResult(r.map(|_| if msg.ban { "banned" } else { "kicked" }.to_owned()))
+    }
+}
+
+/// Lift the bans of the users that were called `target`
+pub struct Unban {
+    pub room: String,
+    /// Client id of the owner or a moderator
+    pub id: usize,
+    pub target: String,
+}
+impl actix::Message for Unban {
+    type Result = Result<String, String>;
+}
+impl Handler<Unban> for Server {
+    type Result = MessageResult<Unban>;
+
+    fn handle(&mut self, msg: Unban, _: &mut Context<Self>) -> Self::Result {
+        if !self.is_moderator(&msg.room, msg.id) {
+            return MessageResult(Err("you are not a moderator".to_owned()));
+        }
+        let bans = match self.rooms.get_mut(&msg.room) {
+            Some(r) => &mut r.bans,
+            None => return MessageResult(Err(format!("{} is not banned", msg.target))),
+        };
+        let count = bans.len();
+        bans.retain(|ban| ban.name != msg.target);
+        if bans.len() == count {
+            return MessageResult(Err(format!("{} is not banned", msg.target)));
+        }
+        MessageResult(Ok("unbanned".to_owned()))
+    }
+}
+
+/// Make the member called `target` a moderator of the room, or take it back
+pub struct Moderator {
+    pub room: String,
+    /// Client id of the owner
+    pub id: usize,
+    pub target: String,
+    pub moderator: bool,
+}
+impl actix::Message for Moderator {
+    type Result = Result<String, String>;
+}
+impl Handler<Moderator> for Server {
+    type Result = MessageResult<Moderator>;
+
+    fn handle(&mut self, msg: Moderator, _: &mut Context<Self>) -> Self::Result {
+        if self.rooms.get(&msg.room).and_then(|r| r.owner) != Some(msg.id) {
+            return MessageResult(Err("you are not the owner".to_owned()));
+        }
+        let target = match self.find_member(&msg.room, &msg.target) {
+            Ok(target) if target != msg.id => target,
+            Ok(_) => return MessageResult(Err("you are the owner".to_owned())),
+            Err(e) => return MessageResult(Err(e)),
+        };
+        let moderators = match self.rooms.get_mut(&msg.room) {
+            Some(r) => &mut r.moderators,
+            None => return MessageResult(Err("you are not the owner".to_owned())),
+        };
+        let data = msg.target.clone();
+        let (event, result) = if msg.moderator {
+            moderators.insert(target);
+            (Event::ModeratorAdded { data }, "added")
+        } else if moderators.remove(&target) {
+            (Event::ModeratorRemoved { data }, "removed")
+        } else {
+            return MessageResult(Err(format!("{} is not a moderator", msg.target)));
+        };
+        self.send_event(&msg.room, &event, 0);
+        MessageResult(Ok(result.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        unix_time, ClientMessage, Connect, CreateInvite, GetMembers, Invite, Join, JoinStatus,
+        Joined, Kick, ListRooms, Member, Message, Password, Player, PrivateMessage, PutDisc,
+        RegistPlayer, Resign, RoomOptions, Server, SetDiscError, SetName, Start, Unban, Visibility,
+        CHAT_HISTORY, CHAT_PAGE, MAX_INVITE_TTL,
+    };
+    use actix::{Actor, Addr, Context, Handler, System, SystemRunner};
+    use serde_json::Value;
+    use std::cell::RefCell;
+    use std::net::IpAddr;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// Client session that keeps every message the server sends it
+    struct Inbox(Rc<RefCell<Vec<Value>>>);
+    impl Actor for Inbox {
+        type Context = Context<Self>;
+    }
+    impl Handler<Message> for Inbox {
+        type Result = ();
+
+        fn handle(&mut self, msg: Message, _: &mut Context<Self>) {
+            self.0
+                .borrow_mut()
+                .push(serde_json::from_str(&msg.0).unwrap());
+        }
+    }
+    impl Handler<Joined> for Inbox {
+        type Result = ();
+
+        fn handle(&mut self, _: Joined, _: &mut Context<Self>) {}
+    }
+
+    /// Round trip through the inbox, everything sent before has arrived
+    struct Flush;
+    impl actix::Message for Flush {
+        type Result = ();
+    }
+    impl Handler<Flush> for Inbox {
+        type Result = ();
+
+        fn handle(&mut self, _: Flush, _: &mut Context<Self>) {}
+    }
+
+    struct Session {
+        id: usize,
+        addr: Addr<Inbox>,
+        inbox: Rc<RefCell<Vec<Value>>>,
+    }
+
+    impl Session {
+        fn connect(sys: &mut SystemRunner, server: &Addr<Server>) -> Self {
+            Self::connect_from(sys, server, None)
+        }
+
+        fn connect_from(
+            sys: &mut SystemRunner,
+            server: &Addr<Server>,
+            peer: Option<IpAddr>,
+        ) -> Self {
+            let inbox = Rc::new(RefCell::new(Vec::new()));
+            let addr = Inbox(inbox.clone()).start();
+            let id = sys
+                .block_on(server.send(Connect {
+                    addr: addr.clone().recipient(),
+                    joined: addr.clone().recipient(),
+                    peer,
+                }))
+                .unwrap();
+            Session { id, addr, inbox }
+        }
+
+        /// Messages received since the last call
+        fn received(&self, sys: &mut SystemRunner) -> Vec<Value> {
+            sys.block_on(self.addr.send(Flush)).unwrap();
+            self.inbox.borrow_mut().drain(..).collect()
+        }
+
+        /// Received messages of the command `cmd`
+        fn received_cmd(&self, sys: &mut SystemRunner, cmd: &str) -> Vec<Value> {
+            self.received(sys)
+                .into_iter()
+                .filter(|m| m["cmd"] == cmd)
+                .collect()
+        }
+    }
+
+    fn join(id: usize, room: &str, options: RoomOptions, invite: Option<&str>) -> Join {
+        Join {
+            id,
+            name: room.to_owned(),
+            options,
+            queue: false,
+            invite: invite.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn create_room() {
+        let mut server = Server::default();
+        assert!(server.game("room1").is_some());
+        assert!(server.game("Main").is_none());
+        assert!(server
+            .create_room("room1", &RoomOptions::default())
+            .is_err());
+
+        let options = |game: Option<&str>, size| RoomOptions {
+            game: game.map(str::to_owned),
+            size,
+            ..RoomOptions::default()
+        };
+        server
+            .create_room("gomoku", &options(Some("gomoku"), Some(19)))
+            .unwrap();
+        assert_eq!(server.game("gomoku").unwrap().kind(), "gomoku");
+        assert_eq!(server.rooms["gomoku"].size, Some(19));
+        assert!(server.create_room("chat", &options(None, None)).is_ok());
+        assert!(server.game("chat").is_none());
+        assert!(server
+            .create_room("a", &options(Some("chess"), None))
+            .is_err());
+        assert!(server
+            .create_room("b", &options(Some("reversi"), Some(7)))
+            .is_err());
+        assert!(server.create_room("c", &options(None, Some(8))).is_err());
+        assert!(!server.rooms.contains_key("b"));
+    }
+
+    #[test]
+    fn reap_rooms() {
+        let mut server = Server::default().with_room_idle_timeout(Duration::from_secs(0));
+        server
+            .create_room("empty", &RoomOptions::default())
+            .unwrap();
+        server.create_room("used", &RoomOptions::default()).unwrap();
+        server.rooms.get_mut("used").unwrap().sessions.insert(1);
+        server.reap_rooms();
+        assert!(!server.rooms.contains_key("empty"));
+        assert!(server.rooms.contains_key("used"));
+        assert!(server.rooms.contains_key("room1"));
+        assert!(server.rooms.contains_key("Main"));
+
+        let mut server = Server::default();
+        server
+            .create_room("empty", &RoomOptions::default())
+            .unwrap();
+        server.reap_rooms();
+        assert!(server.rooms.contains_key("empty"));
+        assert!(server.rooms["empty"].idle_since.is_some());
+    }
+
+    #[test]
+    fn capacity() {
+        let mut server = Server::default();
+        let options = |capacity| RoomOptions {
+            capacity,
+            ..RoomOptions::default()
+        };
+        assert!(server.create_room("none", &options(Some(0))).is_err());
+        server.create_room("pair", &options(Some(2))).unwrap();
+        server.enter(1, "pair");
+        assert!(!server.is_full("pair"));
+        server.enter(2, "pair");
+        assert!(server.is_full("pair"));
+        assert!(!server.is_full("Main"));
+
+        // sessions that have gone away are dropped from the queue
+        server.rooms.get_mut("pair").unwrap().queue.extend([4, 3]);
+        server.leave(1);
+        assert!(!server.is_full("pair"));
+        assert!(server.rooms["pair"].queue.is_empty());
+        server.enter(1, "Main");
+        server.rooms.get_mut("pair").unwrap().queue.push_back(1);
+        server.enter(1, "room1");
+        assert!(server.rooms["pair"].queue.is_empty());
+    }
+
+    #[test]
+    fn private_rooms() {
+        let mut sys = System::new("test");
+        let server = Server::default().start();
+        let a = Session::connect(&mut sys, &server);
+        let b = Session::connect(&mut sys, &server);
+        let options = |visibility, password: Option<&str>| RoomOptions {
+            visibility,
+            password: password.map(str::to_owned),
+            ..RoomOptions::default()
+        };
+        let mut send = |msg| sys.block_on(server.send(msg)).unwrap();
+        let private = options(Visibility::Private, None);
+        assert!(send(join(a.id, "a", private, None)).is_err());
+        let private = options(Visibility::Private, Some("pw"));
+        assert_eq!(send(join(a.id, "a", private, None)), Ok(JoinStatus::Joined));
+        let unlisted = options(Visibility::Unlisted, None);
+        send(join(a.id, "b", unlisted, None)).unwrap();
+        send(join(a.id, "c", RoomOptions::default(), None)).unwrap();
+
+        // the settings of an existing room stay
+        let wrong = Err("wrong password".to_owned());
+        assert_eq!(send(join(b.id, "a", RoomOptions::default(), None)), wrong);
+        let guess = options(Visibility::Public, Some("PW"));
+        assert_eq!(send(join(b.id, "a", guess, None)), wrong);
+        let password = options(Visibility::Public, Some("pw"));
+        assert_eq!(
+            send(join(b.id, "a", password, None)),
+            Ok(JoinStatus::Joined)
+        );
+        let list = sys.block_on(server.send(ListRooms::default())).unwrap();
+        let names: Vec<&str> = list.rooms.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["c", "room1", "room2", "room3", "room4", "room5"]);
+    }
+
+    #[test]
+    fn chat_history() {
+        let mut server = Server::default();
+        let sender = Member {
+            id: "a".to_owned(),
+            name: "alice".to_owned(),
+        };
+        for i in 0..CHAT_HISTORY + 10 {
+            server.record_chat("room1", sender.clone(), &i.to_string());
+        }
+        server.record_chat("room2", sender, "hi");
+        assert_eq!(server.rooms["room1"].chat.len(), CHAT_HISTORY);
+
+        let latest = server.chat_history("room1", None);
+        assert_eq!(latest.len(), CHAT_PAGE);
+        assert_eq!(latest.last().unwrap().text, (CHAT_HISTORY + 9).to_string());
+        let older = server.chat_history("room1", Some(latest[0].id));
+        assert_eq!(older.len(), CHAT_PAGE);
+        assert_eq!(older.last().unwrap().id, latest[0].id - 1);
+        // the oldest messages are gone
+        let first = server.chat_history("room1", Some(server.rooms["room1"].chat[5].id));
+        assert_eq!(first.len(), 5);
+        assert_eq!(first[0].text, "10");
+        assert_eq!(server.chat_history("room2", None)[0].text, "hi");
+        assert!(server.chat_history("room3", None).is_empty());
+    }
+
+    #[test]
+    fn list_rooms() {
+        let mut server = Server::default();
+        for id in 1..=3 {
+            server.enter(id, "room2");
+        }
+        let game = server.game("room2").unwrap();
+        game.set_player(Player::One, 1);
+        game.set_player(Player::Two, 2);
+        game.start(None).unwrap();
+        server.enter(4, "room3");
+        server.game("room3").unwrap().set_player(Player::Two, 4);
+
+        let list = server.list_rooms(&ListRooms::default());
+        assert_eq!(list.total, 5);
+        let room2 = &list.rooms[1];
+        assert_eq!(room2.name, "room2");
+        assert_eq!(room2.members, 3);
+        assert_eq!(room2.spectators, 1);
+        assert_eq!(room2.game, Some("reversi"));
+        assert!(room2.in_progress);
+        assert_eq!(room2.players, [Some("".to_owned()), Some("".to_owned())]);
+        assert_eq!(list.rooms[2].players, [None, Some("".to_owned())]);
+
+        let list = server.list_rooms(&ListRooms {
+            in_progress: true,
+            ..ListRooms::default()
+        });
+        assert_eq!(list.rooms.len(), 1);
+        let list = server.list_rooms(&ListRooms {
+            open_seats: true,
+            offset: 1,
+            limit: Some(2),
+            ..ListRooms::default()
+        });
+        assert_eq!(list.total, 4);
+        let names: Vec<&str> = list.rooms.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["room3", "room4"]);
+    }
+
+    #[test]
+    fn invites() {
+        let mut server = Server::default();
+        let options = RoomOptions {
+            visibility: Visibility::Private,
+            password: Some("pw".to_owned()),
+            ..RoomOptions::default()
+        };
+        server.create_room("a", &options).unwrap();
+        let token = |server: &Server, single_use, expires| {
+            Invite {
+                id: 7,
+                room: "a".to_owned(),
+                expires,
+                single_use,
             }
+            .sign(&server.secret)
+        };
+        let later = unix_time() + 60;
+
+        let single = token(&server, true, later);
+        assert!(server
+            .join(
+                join(1, "b", RoomOptions::default(), Some(&single)),
+                Password::None
+            )
+            .is_err());
+        assert!(!server.rooms.contains_key("b"));
+        assert_eq!(
+            server.join(
+                join(1, "a", RoomOptions::default(), Some(&single)),
+                Password::None
+            ),
+            Ok(JoinStatus::Joined)
+        );
+        assert!(server
+            .join(
+                join(2, "a", RoomOptions::default(), Some(&single)),
+                Password::None
+            )
+            .is_err());
+
+        let expired = token(&server, false, unix_time() - 1);
+        assert!(server
+            .join(
+                join(2, "a", RoomOptions::default(), Some(&expired)),
+                Password::None
+            )
+            .is_err());
+        let forged = Invite {
+            id: 8,
+            room: "a".to_owned(),
+            expires: later,
+            single_use: false,
         }
+        .sign(b"guess");
+        assert!(server
+            .join(
+                join(2, "a", RoomOptions::default(), Some(&forged)),
+                Password::None
+            )
+            .is_err());
+
+        // revoked until the token expires
+        server.revoked.clear();
+        let multi = token(&server, false, later);
+        server
+            .join(
+                join(2, "a", RoomOptions::default(), Some(&multi)),
+                Password::None,
+            )
+            .unwrap();
+        server
+            .join(
+                join(3, "a", RoomOptions::default(), Some(&multi)),
+                Password::None,
+            )
+            .unwrap();
+        server.revoked.insert(7, later);
+        assert!(server
+            .join(
+                join(4, "a", RoomOptions::default(), Some(&multi)),
+                Password::None
+            )
+            .is_err());
+        server.reap_rooms();
+        assert!(server.revoked.contains_key(&7));
+    }
+
+    #[test]
+    fn moderation() {
+        let mut server = Server::default();
+        for id in 1..=4 {
+            server.enter(id, "room1");
+        }
+        assert_eq!(server.rooms["room1"].owner, Some(1));
+        server.game("room1").unwrap().set_player(Player::One, 4);
+        server.rooms.get_mut("room1").unwrap().moderators.insert(2);
+
+        assert!(server.kick("room1", 3, 4, false).is_err());
+        assert!(server.kick("room1", 2, 1, false).is_err());
+        assert!(server.kick("room1", 2, 2, false).is_err());
+        server.kick("room1", 2, 4, false).unwrap();
+        assert!(server.rooms["Main"].sessions.contains(&4));
+        assert_eq!(server.game("room1").unwrap().player(Player::One), 0);
+
+        server.enter(4, "room1");
+        server.kick("room1", 1, 4, true).unwrap();
+        assert!(server
+            .join(
+                join(4, "room1", RoomOptions::default(), None),
+                Password::None
+            )
+            .is_err());
+        assert!(server
+            .join(
+                join(5, "room1", RoomOptions::default(), None),
+                Password::None
+            )
+            .is_ok());
+
+        // a moderator takes over from the owner
+        server.enter(1, "Main");
+        assert_eq!(server.rooms["room1"].owner, Some(2));
+        server.kick("room1", 2, 3, false).unwrap();
+        server.enter(2, "Main");
+        assert_eq!(server.rooms["room1"].owner, Some(5));
+        server.enter(5, "Main");
+        assert_eq!(server.rooms["room1"].owner, None);
+        assert_eq!(server.rooms["Main"].owner, None);
+    }
+
+    #[test]
+    fn put_disc_without_game() {
+        let mut sys = System::new("test");
+        let server = Server::default().start();
+        let put = |room: &str| PutDisc {
+            room: room.to_owned(),
+            id: 1,
+            x: 2,
+            y: 4,
+        };
+        let res = sys.block_on(server.send(put("Main"))).unwrap();
+        assert_eq!(res, Err(SetDiscError::NoGame));
+        let res = sys.block_on(server.send(put("room1"))).unwrap();
+        assert_eq!(res, Err(SetDiscError::NotYourTurn));
+    }
+
+    #[test]
+    fn game_over_once() {
+        let mut sys = System::new("test");
+        let server = Server::default().start();
+        let a = Session::connect(&mut sys, &server);
+        let b = Session::connect(&mut sys, &server);
+        for (session, player) in [(&a, Player::One), (&b, Player::Two)] {
+            sys.block_on(server.send(join(session.id, "room1", RoomOptions::default(), None)))
+                .unwrap()
+                .unwrap();
+            let seat = RegistPlayer {
+                room: "room1".to_owned(),
+                id: session.id,
+                player,
+                bot: None,
+            };
+            assert!(sys.block_on(server.send(seat)).unwrap());
+        }
+        let start = Start {
+            room: "room1".to_owned(),
+            size: None,
+            time: None,
+        };
+        sys.block_on(server.send(start)).unwrap().unwrap();
+        let resign = Resign {
+            room: "room1".to_owned(),
+            id: a.id,
+        };
+        sys.block_on(server.send(resign)).unwrap().unwrap();
+        let over = a.received_cmd(&mut sys, "game_over");
+        assert_eq!(over.len(), 1);
+        assert_eq!(over[0]["data"]["winner"], "player2");
+
+        // later state pushes leave the result out
+        let c = Session::connect(&mut sys, &server);
+        sys.block_on(server.send(join(c.id, "room1", RoomOptions::default(), None)))
+            .unwrap()
+            .unwrap();
+        sys.block_on(server.send(join(c.id, "Main", RoomOptions::default(), None)))
+            .unwrap()
+            .unwrap();
+        let received = a.received(&mut sys);
+        assert!(received.iter().any(|m| m["cmd"] == "update_state"));
+        assert!(received.iter().all(|m| m["cmd"] != "game_over"));
+    }
+
+    #[test]
+    fn invite_ttl() {
+        let mut sys = System::new("test");
+        let server = Server::default();
+        let secret = server.secret;
+        let server = server.start();
+        let a = Session::connect(&mut sys, &server);
+        let invite = |ttl| CreateInvite {
+            room: "Main".to_owned(),
+            id: a.id,
+            single_use: true,
+            ttl,
+        };
+        let token = sys
+            .block_on(server.send(invite(MAX_INVITE_TTL)))
+            .unwrap()
+            .unwrap();
+        let expires = Invite::verify(&token, &secret).unwrap().expires;
+        assert!(expires >= unix_time() + MAX_INVITE_TTL.as_secs() - 1);
+
+        // used to overflow unix_time() + ttl
+        let res = sys
+            .block_on(server.send(invite(Duration::from_secs(u64::MAX))))
+            .unwrap();
+        assert_eq!(res, Err("ttl must be at most 2592000 seconds".to_owned()));
+    }
+
+    #[test]
+    fn bans_outlive_sessions() {
+        let mut sys = System::new("test");
+        let server = Server::default().start();
+        let home: Option<IpAddr> = Some("192.0.2.1".parse().unwrap());
+        let owner = Session::connect(&mut sys, &server);
+        let troll = Session::connect_from(&mut sys, &server, home);
+        sys.block_on(server.send(SetName {
+            id: troll.id,
+            new_name: "troll".to_owned(),
+        }))
+        .unwrap();
+        for id in [owner.id, troll.id] {
+            sys.block_on(server.send(join(id, "bar", RoomOptions::default(), None)))
+                .unwrap()
+                .unwrap();
+        }
+        let kick = Kick {
+            room: "bar".to_owned(),
+            id: owner.id,
+            target: "troll".to_owned(),
+            ban: true,
+        };
+        sys.block_on(server.send(kick)).unwrap().unwrap();
+
+        // a new session from the same address is still banned
+        let again = Session::connect_from(&mut sys, &server, home);
+        let res = sys
+            .block_on(server.send(join(again.id, "bar", RoomOptions::default(), None)))
+            .unwrap();
+        assert_eq!(res, Err("you are banned from this room".to_owned()));
+        // the address is all there is, another one gets in
+        let elsewhere = Session::connect_from(&mut sys, &server, "198.51.100.7".parse().ok());
+        let res = sys
+            .block_on(server.send(join(elsewhere.id, "bar", RoomOptions::default(), None)))
+            .unwrap();
+        assert_eq!(res, Ok(JoinStatus::Joined));
+
+        let unban = Unban {
+            room: "bar".to_owned(),
+            id: owner.id,
+            target: "troll".to_owned(),
+        };
+        sys.block_on(server.send(unban)).unwrap().unwrap();
+        let res = sys
+            .block_on(server.send(join(again.id, "bar", RoomOptions::default(), None)))
+            .unwrap();
+        assert_eq!(res, Ok(JoinStatus::Joined));
+    }
+
+    #[test]
+    fn member_events() {
+        let mut sys = System::new("test");
+        let server = Server::default().start();
+        let a = Session::connect(&mut sys, &server);
+        let b = Session::connect(&mut sys, &server);
+        let mut seen = Vec::new();
+        let mut events = |sys: &mut SystemRunner, cmd: &str| {
+            let received = a.received(sys);
+            seen.extend(received.iter().cloned());
+            received
+                .into_iter()
+                .filter(|m| m["cmd"] == cmd)
+                .collect::<Vec<_>>()
+        };
+
+        let joined = events(&mut sys, "member_joined");
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0]["v"], 1);
+        assert_eq!(joined[0]["room"], "Main");
+        assert_eq!(joined[0]["data"]["name"], "名無し");
+        let b_public = joined[0]["data"]["id"].as_str().unwrap().to_owned();
+        assert_eq!(b_public.len(), 16);
+
+        sys.block_on(server.send(SetName {
+            id: b.id,
+            new_name: "bob".to_owned(),
+        }))
+        .unwrap();
+        let renamed = events(&mut sys, "member_renamed");
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0]["data"]["id"], b_public.as_str());
+        assert_eq!(renamed[0]["data"]["name"], "bob");
+
+        sys.block_on(server.send(join(b.id, "room1", RoomOptions::default(), None)))
+            .unwrap()
+            .unwrap();
+        let left = events(&mut sys, "member_left");
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0]["room"], "Main");
+        assert_eq!(left[0]["data"]["id"], b_public.as_str());
+        assert_eq!(left[0]["data"]["name"], "bob");
+
+        // the newcomer gets the members by public id too
+        let received = b.received(&mut sys);
+        let members = received
+            .iter()
+            .filter(|m| m["cmd"] == "members" && m["room"] == "room1")
+            .collect::<Vec<_>>();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0]["data"][0]["id"], b_public.as_str());
+
+        // session ids never reach other clients
+        let everything = seen
+            .iter()
+            .chain(received.iter())
+            .map(Value::to_string)
+            .collect::<String>();
+        for id in [a.id, b.id] {
+            assert!(!everything.contains(&id.to_string()));
+        }
+    }
+
+    #[test]
+    fn chat_events() {
+        let mut sys = System::new("test");
+        let server = Server::default().start();
+        let a = Session::connect(&mut sys, &server);
+        let b = Session::connect(&mut sys, &server);
+        sys.block_on(server.send(SetName {
+            id: a.id,
+            new_name: "alice".to_owned(),
+        }))
+        .unwrap();
+        let say = |sys: &mut SystemRunner, room: &str| {
+            let msg = ClientMessage {
+                id: a.id,
+                msg: "hi".to_owned(),
+                room: room.to_owned(),
+            };
+            sys.block_on(server.send(msg)).unwrap();
+        };
+        say(&mut sys, "Main");
+        let got = b.received_cmd(&mut sys, "chat");
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0]["room"], "Main");
+        assert_eq!(got[0]["data"]["sender"]["name"], "alice");
+        assert_eq!(got[0]["data"]["text"], "hi");
+        assert!(got[0]["data"]["id"].is_u64());
+        // the sender sees the message as the others do
+        assert_eq!(a.received_cmd(&mut sys, "chat"), got);
+
+        // the history of a new member has it
+        sys.block_on(server.send(join(b.id, "room1", RoomOptions::default(), None)))
+            .unwrap()
+            .unwrap();
+        sys.block_on(server.send(join(b.id, "Main", RoomOptions::default(), None)))
+            .unwrap()
+            .unwrap();
+        let history = b.received_cmd(&mut sys, "chat_history");
+        assert_eq!(history.last().unwrap()["data"][0], got[0]["data"]);
+
+        say(&mut sys, "nowhere");
+        assert!(b.received_cmd(&mut sys, "chat").is_empty());
+    }
+
+    #[test]
+    fn private_messages() {
+        let mut sys = System::new("test");
+        let server = Server::default().start();
+        let sessions: Vec<Session> = (0..4)
+            .map(|_| Session::connect(&mut sys, &server))
+            .collect();
+        for (session, name) in sessions.iter().zip(&["alice", "bob", "carol", "carol"]) {
+            let set_name = SetName {
+                id: session.id,
+                new_name: name.to_string(),
+            };
+            sys.block_on(server.send(set_name)).unwrap();
+        }
+        for session in &sessions {
+            session.received(&mut sys);
+        }
+        let (alice, bob, carol) = (&sessions[0], &sessions[1], &sessions[2]);
+        let send = |sys: &mut SystemRunner, to: &str| {
+            let msg = PrivateMessage {
+                id: alice.id,
+                to: to.to_owned(),
+                text: "psst".to_owned(),
+            };
+            sys.block_on(server.send(msg)).unwrap()
+        };
+
+        assert_eq!(send(&mut sys, "bob"), Ok("delivered".to_owned()));
+        let got = bob.received_cmd(&mut sys, "private_message");
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0]["data"]["from"]["name"], "alice");
+        assert_eq!(got[0]["data"]["to"]["name"], "bob");
+        assert_eq!(got[0]["data"]["text"], "psst");
+        // the sender gets a copy, nobody else hears of it
+        assert_eq!(alice.received_cmd(&mut sys, "private_message"), got);
+        assert!(carol.received(&mut sys).is_empty());
+
+        assert_eq!(send(&mut sys, "dave"), Err("unknown user: dave".to_owned()));
+
+        // a shared name is refused with the ids to choose from
+        let error = send(&mut sys, "carol").unwrap_err();
+        assert!(error.starts_with("more than one user is called carol"));
+        let members = sys
+            .block_on(server.send(GetMembers {
+                room: "Main".to_owned(),
+            }))
+            .unwrap();
+        let carols: Vec<&Member> = members.iter().filter(|m| m.name == "carol").collect();
+        assert_eq!(carols.len(), 2);
+        assert!(carols.iter().all(|m| error.contains(&m.id)));
+        assert!(sessions[2..]
+            .iter()
+            .all(|s| s.received_cmd(&mut sys, "private_message").is_empty()));
+
+        assert_eq!(send(&mut sys, &carols[0].id), Ok("delivered".to_owned()));
+        let delivered: usize = sessions[2..]
+            .iter()
+            .map(|s| s.received_cmd(&mut sys, "private_message").len())
+            .sum();
+        assert_eq!(delivered, 1);
     }
 }
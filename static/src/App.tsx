@@ -20,8 +20,18 @@ const wsUri =
   "/ws/";
 const wsClient = new WebSocket.w3cwebsocket(wsUri);
 
+interface RoomInfo {
+  name: string;
+  members: number;
+  capacity: number | null;
+  game: string | null;
+  in_progress: boolean;
+  players: (string | null)[];
+  spectators: number;
+}
+
 interface states {
-  rooms?: string[];
+  rooms?: RoomInfo[];
   room?: string;
   userName?: string;
 }
@@ -64,6 +74,12 @@ class App extends React.Component<{}, states> {
           this.setState({ rooms: json.data });
           break;
         }
+        case "room_closed": {
+          this.setState({
+            rooms: this.state.rooms?.filter(room => room.name !== json.data)
+          });
+          break;
+        }
       }
     } else {
       console.log(json);
@@ -121,12 +137,16 @@ class App extends React.Component<{}, states> {
                       <ListGroupItem
                         onClick={() => {
                           this.updateName();
-                          this.joinRoom(room);
+                          this.joinRoom(room.name);
                         }}
                         action
-                        key={room}
+                        key={room.name}
                       >
-                        {room}
+                        {room.name}
+                        {room.game && ` (${room.game})`} :{" "}
+                        {room.members}
+                        {room.capacity !== null && `/${room.capacity}`}
+                        {room.in_progress && " playing"}
                       </ListGroupItem>
                     );
                   })}
@@ -14,9 +14,22 @@ interface props {
     wsClient: WebSocket.w3cwebsocket
 }
 
+interface Member {
+    id: string,
+    name: string,
+}
+
+interface ChatMessage {
+    id: number,
+    sender: Member,
+    text: string,
+    time: number,
+}
+
 interface states {
     oldListener?: (message: WebSocket.IMessageEvent) => void,
-    members?: String[],
+    members?: Member[],
+    chat?: ChatMessage[],
     reversi?: reversi,
     player1?: String,
     player2?: String,
@@ -30,6 +43,7 @@ class Game extends React.Component<props, states> {
         this.state = {
             oldListener: props.wsClient.onmessage,
             members: [],
+            chat: [],
             reversi: new reversi(),
             player1: "",
             player2: "",
@@ -38,6 +52,7 @@ class Game extends React.Component<props, states> {
         }
         this.props.wsClient.onmessage = (e) => { this.onMessage(e); };
         this.props.wsClient.send("/members");
+        this.props.wsClient.send("/chatlog");
         this.updatePlayers();
     }
     componentDidMount() {
@@ -61,6 +76,34 @@ class Game extends React.Component<props, states> {
                     this.setState({ members: json.data });
                     break;
                 }
+                case 'member_joined': {
+                    this.setState({ members: [...(this.state.members ?? []), json.data] });
+                    break;
+                }
+                case 'member_left': {
+                    this.setState({
+                        members: this.state.members?.filter(member => member.id !== json.data.id)
+                    });
+                    this.updatePlayers();
+                    break;
+                }
+                case 'member_renamed': {
+                    this.setState({
+                        members: this.state.members?.map(member =>
+                            member.id === json.data.id ? { ...member, name: json.data.name } : member
+                        )
+                    });
+                    this.updatePlayers();
+                    break;
+                }
+                case 'chat_history': {
+                    this.setState({ chat: json.data });
+                    break;
+                }
+                case 'chat': {
+                    this.setState({ chat: [...(this.state.chat ?? []), json.data] });
+                    break;
+                }
                 case 'registered_player1' :{
                     this.setState({ player1: json.data });
                     break;
@@ -78,10 +121,8 @@ class Game extends React.Component<props, states> {
                     }
                 }
                 case 'player' :{
-                    if (json.sub_cmd === 'get') {
-                        if (json.data.p === 1) this.setState({ player1: json.data.data });
-                        if (json.data.p === 2) this.setState({ player2: json.data.data });
-                    }
+                    if (json.seat === 1) this.setState({ player1: json.data });
+                    if (json.seat === 2) this.setState({ player2: json.data });
                     break;
                 }
                 case 'update_state': {
@@ -90,10 +131,6 @@ class Game extends React.Component<props, states> {
                 }
             }
         } else {
-            if (e.data === "Someone connected" || e.data === "Someone disconnected") {
-                this.props.wsClient.send("/members");
-                this.updatePlayers();
-            }
             console.log(e.data);
         }
     }
@@ -147,7 +184,14 @@ class Game extends React.Component<props, states> {
                 <Row>
                     <ListGroup>
                         {this.state.members?.map(member => {
-                            return <ListGroupItem>{member}</ListGroupItem>;
+                            return <ListGroupItem key={member.id}>{member.name}</ListGroupItem>;
+                        })}
+                    </ListGroup>
+                </Row>
+                <Row>
+                    <ListGroup>
+                        {this.state.chat?.map(message => {
+                            return <ListGroupItem key={message.id}>{message.sender.name}: {message.text}</ListGroupItem>;
                         })}
                     </ListGroup>
                 </Row>
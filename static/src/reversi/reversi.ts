@@ -64,6 +64,8 @@ export default class _reversi extends _ctx {
     }
     public setState(states: number[][]) {
         this._states = states;
+        this._height = states.length;
+        this._width = states.length > 0 ? states[0].length : this._height;
     }
     public draw() {
         for (let y = 0; y < this._height; y++) {